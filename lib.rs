@@ -1,20 +1,129 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 use ink_lang as ink;
 
+pub use self::erc721::{Erc721, Error, TokenId};
+
+/// Core ERC-721 interface for calls from other contracts (staking, lending, games).
+#[ink::trait_definition]
+pub trait Erc721Core {
+    /// Returns the owner of the token.
+    #[ink(message)]
+    fn owner_of(&self, id: TokenId) -> Option<ink_env::AccountId>;
+
+    /// Returns the balance of the owner.
+    #[ink(message)]
+    fn balance_of(&self, owner: ink_env::AccountId) -> u32;
+
+    /// Transfer approved or owned token, paying the transfer fee of the collection.
+    #[ink(message, payable)]
+    fn transfer_from(&mut self, from: ink_env::AccountId, to: ink_env::AccountId, id: TokenId) -> Result<(), Error>;
+
+    /// Approves the account to transfer the specified token on behalf of the caller.
+    #[ink(message)]
+    fn approve(&mut self, to: ink_env::AccountId, id: TokenId) -> Result<(), Error>;
+}
+
 #[ink::contract]
 mod erc721 {
     use ink_storage::traits::SpreadAllocate;
     use ink_storage::Mapping;
 
+    use ink_prelude::string::String;
     use ink_prelude::vec::Vec;
     use scale::{Decode, Encode};
 
     /// A token ID.
-    pub type TokenId = u32;
+    ///
+    /// Wide enough for composite ids, e.g. the high 64 bits encode a series
+    /// and the low 64 bits the serial number within it.
+    pub type TokenId = u128;
+
+    /// Version of the storage layout, bumped on incompatible changes.
+    pub const STORAGE_VERSION: u32 = 2;
+
+    /// Basis points of the whole amount.
+    pub const MAX_BPS: u16 = 10_000;
+
+    /// Max length of memo attached to a transfer.
+    pub const MAX_MEMO_LEN: usize = 256;
+
+    /// Max length of name of the collection in bytes.
+    pub const MAX_NAME_LEN: usize = 64;
+
+    /// Max length of symbol of the collection in bytes.
+    pub const MAX_SYMBOL_LEN: usize = 16;
+
+    /// Max number of sales kept in history of one token.
+    pub const MAX_SALE_HISTORY: usize = 32;
+
+    /// Max number of standing offers for one token.
+    pub const MAX_OFFERS_PER_TOKEN: usize = 32;
+
+    /// Max number of ids processed by one batch query.
+    pub const MAX_BATCH_SIZE: usize = 100;
+
+    /// Max number of listings returned by one page.
+    pub const MAX_PAGE_SIZE: u32 = 100;
+
+    /// Royalty cap in basis points of contracts deployed without a configured cap.
+    pub const DEFAULT_MAX_ROYALTY_BPS: u16 = 1_000;
+
+    /// Max number of id ranges revealed in stages.
+    pub const MAX_REVEALED_RANGES: usize = 64;
+
+    /// Max number of latest sales of all tokens kept for `sales_since`.
+    pub const MAX_RECENT_SALES: u32 = 1_024;
+
+    /// Gas limit of the transfer hook call of contracts deployed without a configured limit.
+    pub const DEFAULT_TRANSFER_HOOK_GAS_LIMIT: u64 = 5_000_000_000;
+
+    /// Max gas limit of the transfer hook call the collection owner can set.
+    pub const MAX_TRANSFER_HOOK_GAS_LIMIT: u64 = 50_000_000_000;
+
+    /// Max number of recipients sharing mint revenue.
+    pub const MAX_REVENUE_SHARES: usize = 16;
+
+    /// Interface id of core ERC-721 messages, see `Erc721Core`.
+    pub const INTERFACE_ERC721: [u8; 4] = ink_lang::selector_bytes!("Erc721Core");
+
+    /// Interface id of token metadata messages (`token_uri`, attributes).
+    pub const INTERFACE_METADATA: [u8; 4] = ink_lang::selector_bytes!("Erc721Metadata");
+
+    /// Interface id of enumeration messages (`total_supply`, `tokens_of_owner`).
+    pub const INTERFACE_ENUMERABLE: [u8; 4] = ink_lang::selector_bytes!("Erc721Enumerable");
+
+    /// Interface id of royalty messages (`royalty_info`).
+    pub const INTERFACE_ROYALTIES: [u8; 4] = ink_lang::selector_bytes!("Erc721Royalties");
+
+    /// Interface id of marketplace messages (listings, auctions, offers).
+    pub const INTERFACE_MARKETPLACE: [u8; 4] = ink_lang::selector_bytes!("Erc721Marketplace");
+
+    /// Interface ids reported by `supports_interface`.
+    const SUPPORTED_INTERFACES: [[u8; 4]; 5] = [
+        INTERFACE_ERC721,
+        INTERFACE_METADATA,
+        INTERFACE_ENUMERABLE,
+        INTERFACE_ROYALTIES,
+        INTERFACE_MARKETPLACE,
+    ];
 
     #[ink(storage)]
     #[derive(Default, SpreadAllocate)]
     pub struct Erc721 {
+        /// Owner of the collection.
+        owner: AccountId,
+        /// Account which can accept ownership of the collection.
+        pending_owner: Option<AccountId>,
+        /// Name of the collection.
+        name: String,
+        /// Symbol of the collection.
+        symbol: String,
+        /// Prefix of URI of tokens without own URI, followed by token id.
+        base_uri: Option<String>,
+        /// URI of tokens not revealed yet, metadata is hidden until revealed if set
+        placeholder_uri: Option<String>,
+        /// revealed id ranges as (from id, to id inclusive, base URI)
+        revealed_ranges: Vec<(TokenId, TokenId, String)>,
         /// Mapping from token to owner.
         token_owner: Mapping<TokenId, AccountId>,
         /// Mapping from owner to all tokens
@@ -23,6 +132,24 @@ mod erc721 {
         owned_tokens_count: Mapping<AccountId, u32>,
         /// Token metadata
         token_data: Mapping<TokenId, NftData>,
+        /// metadata shared by many tokens
+        templates: Mapping<u32, NftData>,
+        /// id of the next created template
+        next_template_id: u32,
+        /// template of token minted without own metadata
+        token_templates: Mapping<TokenId, u32>,
+        /// IPFS content identifier of token metadata
+        token_cids: Mapping<TokenId, Cid>,
+        /// attribute values of token by key
+        attributes: Mapping<(TokenId, String), String>,
+        /// keys of attributes set on token
+        attribute_keys: Mapping<TokenId, Vec<String>>,
+        /// tokens by attribute key and value
+        attribute_index: Mapping<(String, String), Vec<TokenId>>,
+        /// tokens which metadata cannot be changed anymore
+        frozen_metadata: Mapping<TokenId, ()>,
+        /// true once metadata of the whole collection cannot be changed anymore
+        metadata_frozen: bool,
         /// All tokens id
         all_tokens: Vec<TokenId>,
         
@@ -30,6 +157,142 @@ mod erc721 {
         prices: Mapping<TokenId, Balance>,
         /// tokens which published for sale
         tokens_for_sale: Vec<TokenId>,
+
+        /// tokens of bundle sold as one lot
+        bundles: Mapping<u32, Vec<TokenId>>,
+        /// prices of bundles
+        bundle_prices: Mapping<u32, Balance>,
+        /// account which published bundle
+        bundle_sellers: Mapping<u32, AccountId>,
+        /// id of the next published bundle
+        next_bundle_id: u32,
+        /// minimal price of listing, no limit if none
+        floor_price: Option<Balance>,
+
+        /// accounts allowed to mint reserved tokens
+        minters: Mapping<AccountId, ()>,
+        /// id assigned by the next sequential mint
+        next_token_id: TokenId,
+        /// max number of existing tokens, no limit if none
+        max_supply: Option<u32>,
+        /// max number of tokens one account can mint over its lifetime, no limit if none
+        max_per_account: Option<u32>,
+        /// number of tokens minted by account via `mint`/`mint_next`
+        minted_count: Mapping<AccountId, u32>,
+        /// true once minting is closed forever
+        supply_locked: bool,
+        /// fee paid to the fee recipient for every token minted via `mint`/`mint_next`
+        mint_fee: Balance,
+        /// fee paid to the fee recipient for every token burned via `burn`
+        burn_fee: Balance,
+        /// fee paid to the fee recipient for every token transferred via `transfer`/`transfer_from`
+        transfer_fee: Balance,
+        /// recipients splitting mint fees with their basis points, fees go to the fee recipient if empty
+        revenue_shares: Vec<(AccountId, u16)>,
+        /// amount credited to account which it can withdraw
+        pending_withdrawals: Mapping<AccountId, Balance>,
+
+        /// account which minted the token
+        creators: Mapping<TokenId, AccountId>,
+        /// royalty recipients of token with their basis points
+        royalties: Mapping<TokenId, Vec<(AccountId, u16)>>,
+        /// max sum of royalty basis points of token, higher royalties are scaled down on sale
+        max_royalty_bps: u16,
+        /// royalty recipient with basis points of tokens without own royalty
+        default_royalty: Option<(AccountId, u16)>,
+
+        /// tokens sold with declining price
+        dutch_auctions: Mapping<TokenId, DutchAuction>,
+
+        /// Mapping from token to approved account.
+        token_approvals: Mapping<TokenId, AccountId>,
+        /// Mapping from owner to operator approvals.
+        operator_approvals: Mapping<(AccountId, AccountId), ()>,
+        /// Mapping from owner to operators approved for all tokens.
+        operators_list: Mapping<AccountId, Vec<AccountId>>,
+        /// Number of permits used by owner, signed into the next permit.
+        nonces: Mapping<AccountId, u64>,
+        /// nonces of mint vouchers redeemed by creator
+        redeemed_vouchers: Mapping<(AccountId, u64), ()>,
+
+        /// accounts which published tokens for sale
+        sellers: Mapping<TokenId, AccountId>,
+        /// tokens held by the contract while published for sale
+        custodial_listings: Mapping<TokenId, ()>,
+        /// tokens published for sale which can not be bought until released
+        held_listings: Mapping<TokenId, ()>,
+        /// tokens present in `tokens_for_sale`
+        sale_index: Mapping<TokenId, ()>,
+        /// lowest price of public listings in `tokens_for_sale`
+        lowest_listing_price: Option<Balance>,
+        /// only account allowed to buy token published for sale privately
+        private_buyers: Mapping<TokenId, AccountId>,
+        /// number of private listings in `tokens_for_sale`
+        private_listings_count: u32,
+        /// number of tokens published for sale by account
+        listings_count_by_seller: Mapping<AccountId, u32>,
+
+        /// running English auctions
+        auctions: Mapping<TokenId, Auction>,
+
+        /// escrowed offers for token by offeror with the last block they can be accepted
+        offers: Mapping<(TokenId, AccountId), (Balance, BlockNumber)>,
+        /// accounts with standing offers for token
+        offerors: Mapping<TokenId, Vec<AccountId>>,
+        /// highest standing offer for token
+        best_offers: Mapping<TokenId, (AccountId, Balance)>,
+
+        /// blocks transfers of blacklisted accounts if enabled
+        blacklist_enabled: bool,
+        /// accounts which cannot send or receive tokens
+        blacklist: Mapping<AccountId, ()>,
+
+        /// min number of blocks between transfers of the same token, no limit if none
+        cooldown_blocks: Option<BlockNumber>,
+        /// account which staked the token in place
+        staked_by: Mapping<TokenId, AccountId>,
+        /// operator which locked the staked token and can unlock it
+        stake_operators: Mapping<TokenId, AccountId>,
+        /// block of the last transfer or mint of token
+        last_transfer: Mapping<TokenId, BlockNumber>,
+
+        /// marketplace fee kept from every sale in basis points
+        fee_bps: u16,
+        /// marketplace fees kept in native balance and not withdrawn yet
+        accrued_fees: Balance,
+        /// marketplace fees kept in the payment token and not withdrawn yet
+        accrued_token_fees: Balance,
+        /// account receiving mint fees
+        fee_recipient: AccountId,
+        /// PSP22 token paying for `buy_nft` sales with their fees and royalties, native balance if none
+        payment_token: Option<AccountId>,
+
+        /// sum of prices of all sold tokens
+        total_volume: Balance,
+        /// token sold for the highest price and the price
+        highest_sale: Option<(TokenId, Balance)>,
+        /// last sales of token as (seller, buyer, price, block)
+        sale_history: Mapping<TokenId, Vec<(AccountId, AccountId, Balance, BlockNumber)>>,
+        /// latest sales of all tokens as (token, seller, buyer, price, block) by sale number
+        /// modulo `MAX_RECENT_SALES`
+        recent_sales: Mapping<u32, (TokenId, AccountId, AccountId, Balance, BlockNumber)>,
+        /// number of sales recorded in `recent_sales`
+        sales_count: u32,
+
+        /// total royalty paid to account from sales
+        royalties_earned: Mapping<AccountId, Balance>,
+
+        /// external contract and token id held in custody for wrapped token
+        wrapped: Mapping<TokenId, (AccountId, u32)>,
+
+        /// emit one `BatchTransfer` instead of a `Transfer` per token of a batch
+        batch_transfer_events: bool,
+        /// tokens can be published for sale at price 0
+        allow_free_listings: bool,
+        /// contract notified with `on_transfer` of every token transfer
+        transfer_hook: Option<AccountId>,
+        /// gas available to the transfer hook call
+        transfer_hook_gas_limit: u64,
     }
 
     #[derive(
@@ -44,10 +307,22 @@ mod erc721 {
     pub struct ForSale {
         id: TokenId,
         price: Balance,
+        on_hold: bool,
+    }
+
+    /// Split of the price paid for a listed token.
+    #[derive(scale::Decode, scale::Encode, Debug, PartialEq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct BuyBreakdown {
+        price: Balance,
+        marketplace_fee: Balance,
+        royalty: Balance,
+        seller_proceeds: Balance,
     }
 
     #[derive(Encode, Decode, Debug, PartialEq, Eq, Copy, Clone)]
     #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    #[allow(clippy::upper_case_acronyms)]
     pub enum Error {
         LOL,
         CannotParseMetadata,
@@ -63,6 +338,37 @@ mod erc721 {
         NotEnoughSent,
         CannotMakeTransfer,
         CannotTransferToken,
+        BelowFloor,
+        MaxSupplyReached,
+        MintLimitReached,
+        InvalidRoyalty,
+        MemoTooLong,
+        InvalidSignature,
+        PermitExpired,
+        Overflow,
+        MarketplaceNotApproved,
+        Blacklisted,
+        CooldownActive,
+        MetadataFrozen,
+        OfferNotFound,
+        TooManyOffers,
+        ListingExpired,
+        BidTooLow,
+        AuctionEnded,
+        AuctionNotEnded,
+        InsufficientBalance,
+        SupplyLocked,
+        TokenStaked,
+        VoucherRedeemed,
+        NotWhitelisted,
+        RoyaltyTooHigh,
+        SelfTransfer,
+        ListingOnHold,
+        InvalidPrice,
+        InvalidShares,
+        TemplateNotFound,
+        NameTooLong,
+        SymbolTooLong,
     }
 
     #[derive(
@@ -78,6 +384,146 @@ mod erc721 {
         poebat: Option<ink_prelude::string::String>,
     }
 
+    /// Complete state of a token returned by `token_info`.
+    #[derive(scale::Decode, scale::Encode, Debug, PartialEq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct TokenInfo {
+        owner: AccountId,
+        metadata: NftData,
+        approved: Option<AccountId>,
+        for_sale: bool,
+        price: Option<Balance>,
+        creator: Option<AccountId>,
+    }
+
+    /// Listing joined with its token metadata and fees returned by `detailed_listings`.
+    #[derive(scale::Decode, scale::Encode, Debug, PartialEq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct DetailedListing {
+        id: TokenId,
+        seller: AccountId,
+        price: Balance,
+        metadata: NftData,
+        royalty_bps: u16,
+        fee_bps: u16,
+    }
+
+    /// Off-chain signed permission of `creator` to mint a token to whoever pays `min_price`.
+    #[derive(scale::Decode, scale::Encode, Debug, PartialEq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct MintVoucher {
+        /// Id of the token, the next sequential id if none.
+        pub id: Option<TokenId>,
+        pub metadata: NftData,
+        pub min_price: Balance,
+        pub creator: AccountId,
+        /// Makes every voucher of the creator redeemable only once.
+        pub nonce: u64,
+    }
+
+    /// Settings of a collection applied at deployment by `new_configured`.
+    #[derive(scale::Decode, scale::Encode, Debug, Default, PartialEq)]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Config {
+        pub name: String,
+        pub symbol: String,
+        /// Marketplace fee in basis points, at most `MAX_BPS`.
+        pub fee_bps: u16,
+        /// Account receiving mint fees, the deployer if none.
+        pub fee_recipient: Option<AccountId>,
+        pub max_supply: Option<u32>,
+        pub base_uri: Option<String>,
+        /// Max sum of royalty basis points of token, `DEFAULT_MAX_ROYALTY_BPS` if none.
+        pub max_royalty_bps: Option<u16>,
+        /// Emit one `BatchTransfer` per batch instead of a `Transfer` per token. Cheaper for
+        /// bulk operations, but indexers and wallets which only follow ERC-721 `Transfer` events
+        /// will not see tokens moved by `batch_transfer_from` and `reserve_mint`.
+        pub batch_transfer_events: bool,
+        /// Allow publishing tokens for sale at price 0, which lets anyone take them for free.
+        pub allow_free_listings: bool,
+    }
+
+    /// Length of an IPFS CIDv0: sha2-256 multihash code, digest length and 32 byte digest.
+    pub const CID_LEN: usize = 34;
+
+    /// IPFS content identifier of token metadata.
+    #[derive(
+        scale::Decode,
+        scale::Encode,
+        Debug,
+        PartialEq,
+        Clone,
+        ink_storage::traits::SpreadLayout,
+        ink_storage::traits::PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Cid([u8; CID_LEN]);
+
+    impl Cid {
+        /// Parses multihash bytes of a sha2-256 CIDv0.
+        pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+            let bytes: [u8; CID_LEN] = bytes.try_into().map_err(|_| Error::CannotParseMetadata)?;
+            if bytes[0] != 0x12 || bytes[1] != 0x20 {
+                return Err(Error::CannotParseMetadata);
+            }
+            Ok(Cid(bytes))
+        }
+
+        /// Returns `ipfs://` URI of the content.
+        pub fn to_uri(&self) -> String {
+            let mut uri = String::from("ipfs://");
+            uri.push_str(&base58(&self.0));
+            uri
+        }
+    }
+
+    /// Sale with price declining linearly from `start_price` to `end_price`.
+    #[derive(
+        scale::Decode,
+        scale::Encode,
+        Debug,
+        PartialEq,
+        ink_storage::traits::SpreadLayout,
+        ink_storage::traits::PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct DutchAuction {
+        seller: AccountId,
+        start_price: Balance,
+        end_price: Balance,
+        start_block: BlockNumber,
+        duration: BlockNumber,
+    }
+
+    impl DutchAuction {
+        /// Returns price of auction at `block`.
+        fn price_at(&self, block: BlockNumber) -> Balance {
+            let elapsed = block.saturating_sub(self.start_block).min(self.duration);
+            let (elapsed, duration) = (Balance::from(elapsed), Balance::from(self.duration));
+            let drop = self.start_price - self.end_price;
+            self.start_price - (drop / duration * elapsed + drop % duration * elapsed / duration)
+        }
+    }
+
+    /// English auction of token held by the contract until settlement.
+    #[derive(
+        scale::Decode,
+        scale::Encode,
+        Debug,
+        PartialEq,
+        ink_storage::traits::SpreadLayout,
+        ink_storage::traits::PackedLayout,
+    )]
+    #[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+    pub struct Auction {
+        seller: AccountId,
+        reserve: Balance,
+        min_increment: Balance,
+        buy_now_price: Option<Balance>,
+        end_block: BlockNumber,
+        highest_bid: Option<(AccountId, Balance)>,
+    }
+
     /// Event emitted when a token transfer occurs.
     #[ink(event)]
     pub struct Transfer {
@@ -89,14 +535,167 @@ mod erc721 {
         id: TokenId,
     }
 
+    /// Event emitted once for all tokens moved by a batch when `batch_transfer_events` is set.
+    #[ink(event)]
+    pub struct BatchTransfer {
+        #[ink(topic)]
+        from: Option<AccountId>,
+        #[ink(topic)]
+        to: Option<AccountId>,
+        ids: Vec<TokenId>,
+    }
+
+    /// Event emitted when a token is transferred with a memo.
+    #[ink(event)]
+    pub struct TransferWithMemo {
+        #[ink(topic)]
+        from: Option<AccountId>,
+        #[ink(topic)]
+        to: Option<AccountId>,
+        #[ink(topic)]
+        id: TokenId,
+        memo: Vec<u8>,
+    }
+
+    /// Event emitted when a token approve occurs.
+    #[ink(event)]
+    pub struct Approval {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+        #[ink(topic)]
+        id: TokenId,
+    }
+
+    /// Event emitted when an operator is enabled or disabled for an owner.
+    /// The operator can manage all NFTs of the owner.
+    #[ink(event)]
+    pub struct ApprovalForAll {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        operator: AccountId,
+        approved: bool,
+    }
+
+    /// Event emitted when an auction ends below its reserve and the token returns to the seller.
+    #[ink(event)]
+    pub struct AuctionFailed {
+        #[ink(topic)]
+        id: TokenId,
+        highest_bid: Option<(AccountId, Balance)>,
+        block: BlockNumber,
+        timestamp: Timestamp,
+    }
+
+    /// Event emitted when a token is published for sale for `price`.
+    #[ink(event)]
+    pub struct Listed {
+        #[ink(topic)]
+        seller: AccountId,
+        #[ink(topic)]
+        id: TokenId,
+        price: Balance,
+        block: BlockNumber,
+        timestamp: Timestamp,
+    }
+
+    /// Event emitted when a token published for sale is bought for `price`.
+    #[ink(event)]
+    pub struct Sold {
+        #[ink(topic)]
+        seller: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        #[ink(topic)]
+        id: TokenId,
+        price: Balance,
+        block: BlockNumber,
+        timestamp: Timestamp,
+    }
+
+    /// Event emitted when a token is removed from sale by its seller.
+    #[ink(event)]
+    pub struct Unlisted {
+        #[ink(topic)]
+        seller: AccountId,
+        #[ink(topic)]
+        id: TokenId,
+        block: BlockNumber,
+        timestamp: Timestamp,
+    }
+
+    /// Event emitted when metadata of token is replaced.
+    #[ink(event)]
+    pub struct MetadataUpdated {
+        #[ink(topic)]
+        id: TokenId,
+    }
+
+    /// Event emitted when name or symbol of the collection is replaced.
+    #[ink(event)]
+    pub struct CollectionMetadataUpdated {
+        name: String,
+        symbol: String,
+    }
+
+    /// Event emitted when `account` loses minter rights.
+    #[ink(event)]
+    pub struct MinterRemoved {
+        #[ink(topic)]
+        account: AccountId,
+    }
+
+    /// Event emitted when payment of `amount` to `to` fails. Sale proceeds and royalties `to` rejects
+    /// are credited to it to `withdraw` instead, other payouts fail with the error.
+    #[ink(event)]
+    pub struct TransferFailed {
+        #[ink(topic)]
+        to: AccountId,
+        amount: Balance,
+    }
+
     impl Erc721 {
         /// Creates a new ERC-721 token contract.
         #[ink(constructor)]
         pub fn new() -> Self {
             // This call is required to correctly initialize the
             // Mapping of the contract.
-            ink_lang::utils::initialize_contract(|_: &mut Self| {
-                // let caller = Self::env().caller();
+            ink_lang::utils::initialize_contract(Self::init)
+        }
+
+        /// Creates a new ERC-721 token contract which blocks transfers of blacklisted accounts.
+        #[ink(constructor)]
+        pub fn new_with_blacklist() -> Self {
+            ink_lang::utils::initialize_contract(|contract: &mut Self| {
+                Self::init(contract);
+                contract.blacklist_enabled = true;
+            })
+        }
+
+        /// Creates a new ERC-721 token contract with all settings of `config`.
+        ///
+        /// Panics if the fee of `config` exceeds `MAX_BPS`.
+        #[ink(constructor)]
+        pub fn new_configured(config: Config) -> Self {
+            assert!(config.fee_bps <= MAX_BPS, "fee exceeds MAX_BPS");
+            assert!(config.max_royalty_bps.unwrap_or(0) <= MAX_BPS, "royalty cap exceeds MAX_BPS");
+            ink_lang::utils::initialize_contract(|contract: &mut Self| {
+                Self::init(contract);
+                contract.name = config.name;
+                contract.symbol = config.symbol;
+                contract.fee_bps = config.fee_bps;
+                if let Some(fee_recipient) = config.fee_recipient {
+                    contract.fee_recipient = fee_recipient;
+                }
+                contract.max_supply = config.max_supply;
+                contract.base_uri = config.base_uri;
+                if let Some(max_royalty_bps) = config.max_royalty_bps {
+                    contract.max_royalty_bps = max_royalty_bps;
+                }
+                contract.batch_transfer_events = config.batch_transfer_events;
+                contract.allow_free_listings = config.allow_free_listings;
             })
         }
 
@@ -105,453 +704,6244 @@ mod erc721 {
         //     ink_lang::utils::initialize_contract(|_| {})
         // }
 
-        /// Returns the balance of the owner.
-        ///
-        /// This represents the amount of unique tokens the owner has.
+        /// Returns the version of the storage layout.
         #[ink(message)]
-        pub fn balance_of(&self, owner: AccountId) -> u32 {
-            self.balance_of_or_zero(&owner)
+        pub fn storage_version(&self) -> u32 {
+            STORAGE_VERSION
         }
 
-        /// Returns the owner of the token.
+        /// Returns the name of the collection.
         #[ink(message)]
-        pub fn owner_of(&self, id: TokenId) -> Option<AccountId> {
-            self.token_owner.get(id)
+        pub fn name(&self) -> String {
+            self.name.clone()
         }
 
-        /// Return all tokens of owner
+        /// Returns the symbol of the collection.
         #[ink(message)]
-        pub fn tokens_of_owner(&self, owner: AccountId) -> Vec<TokenId> {
-            self.owned_tokens.get(owner).unwrap_or_default()
+        pub fn symbol(&self) -> String {
+            self.symbol.clone()
         }
 
-        /// Return all tokens
+        /// Set name of the collection. Only the collection owner can call it.
         #[ink(message)]
-        pub fn get_all_tokens(&self) -> Vec<(TokenId, NftData)> {
-            self.all_tokens.iter().map(|id: &TokenId| (*id, self.token_data.get(id).unwrap())).collect()
+        pub fn set_name(&mut self, name: String) -> Result<(), Error> {
+            self.ensure_owner()?;
+            if name.len() > MAX_NAME_LEN {
+                return Err(Error::NameTooLong);
+            }
+            self.name = name;
+            self.emit_collection_metadata_updated();
+            Ok(())
         }
 
-        /// Transfers the token from the caller to the given destination.
+        /// Set symbol of the collection. Only the collection owner can call it.
         #[ink(message)]
-        pub fn transfer(&mut self, destination: AccountId, id: TokenId) -> Result<(), Error> {
-            let caller = self.env().caller();
-            self.transfer_token_from(&caller, &destination, id)?;
+        pub fn set_symbol(&mut self, symbol: String) -> Result<(), Error> {
+            self.ensure_owner()?;
+            if symbol.len() > MAX_SYMBOL_LEN {
+                return Err(Error::SymbolTooLong);
+            }
+            self.symbol = symbol;
+            self.emit_collection_metadata_updated();
             Ok(())
         }
 
-        /// Transfer owned token.
+        fn emit_collection_metadata_updated(&self) {
+            self.env().emit_event(CollectionMetadataUpdated {
+                name: self.name.clone(),
+                symbol: self.symbol.clone(),
+            });
+        }
+
+        /// Returns prefix of URI of tokens without own URI.
         #[ink(message)]
-        pub fn transfer_from(
-            &mut self,
-            from: AccountId,
-            to: AccountId,
-            id: TokenId,
-        ) -> Result<(), Error> {
-            self.transfer_token_from(&from, &to, id)?;
+        pub fn base_uri(&self) -> Option<String> {
+            self.base_uri.clone()
+        }
+
+        /// Set prefix of URI of tokens without own URI. Only the collection owner can call it
+        /// until metadata of the collection is frozen.
+        #[ink(message)]
+        pub fn set_base_uri(&mut self, base_uri: Option<String>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.ensure_collection_not_frozen()?;
+            self.base_uri = base_uri;
             Ok(())
         }
 
-        /// Creates a new token.
+        /// Returns the owner of the collection.
         #[ink(message)]
-        pub fn mint(&mut self, id: TokenId, data: NftData) -> Result<(), Error> {
-            let caller = self.env().caller();
+        pub fn owner(&self) -> AccountId {
+            self.owner
+        }
 
-            self.add_token_to(&caller, id)?;
-            self.token_data.insert(id, &data);
-            self.all_tokens.push(id);
-            self.env().emit_event(Transfer {
-                from: Some(AccountId::from([0x0; 32])),
-                to: Some(caller),
-                id,
-            });
+        /// Returns the account which can accept ownership of the collection.
+        #[ink(message)]
+        pub fn pending_owner(&self) -> Option<AccountId> {
+            self.pending_owner
+        }
+
+        /// Offer ownership of the collection to `new_owner`, who has to accept it.
+        /// Only the collection owner can call it.
+        #[ink(message)]
+        pub fn transfer_ownership(&mut self, new_owner: AccountId) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.pending_owner = Some(new_owner);
             Ok(())
         }
-        
-        /// add token id for sale 
+
+        /// Accept ownership of the collection. Only the pending owner can call it.
         #[ink(message)]
-        pub fn publish_for_sale(&mut self, id: TokenId, price: Balance) -> Result<(), Error> {
+        pub fn accept_ownership(&mut self) -> Result<(), Error> {
             let caller = self.env().caller();
-            if !self.exists(id) {
-                return Err(Error::TokenNotFound);
-            };
-            if !self.is_owner_of(Some(caller), id) {
-                return Err(Error::NotApproved);
-            };
-            if self.prices.contains(id) {
-                return Err(Error::AlreadyForSale);
+            if self.pending_owner != Some(caller) {
+                return Err(Error::NotAllowed);
             }
-            
-            self.tokens_for_sale.push(id);
-            self.prices.insert(id, &price);
-            
+            self.owner = caller;
+            self.pending_owner = None;
             Ok(())
         }
 
-        /// get all tokens which published for sale
+        /// Withdraw offer of ownership of the collection. Only the collection owner can call it.
         #[ink(message)]
-        pub fn get_tokens_for_sale(&self) -> Vec<ForSale> {
-            let mut res = Vec::new();
-            for val in self.tokens_for_sale.iter() {
-                if self.prices.contains(val) {
-                    res.push(ForSale{
-                        id: *val,
-                        price: self.prices.get(val).unwrap(),
-                    });
-                }
-            }
-            res
+        pub fn cancel_ownership_transfer(&mut self) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.pending_owner = None;
+            Ok(())
         }
 
-        /// remove tokens from saling
-        #[ink(message)]
-        pub fn remove_from_sale(&mut self, id: TokenId) -> Result<(), Error>{
-            let caller = self.env().caller();
-            if !self.exists(id) {
-                return Err(Error::TokenNotFound);
-            };
-            if !self.is_owner_of(Some(caller), id) {
-                return Err(Error::NotApproved);
-            };
-            if !self.prices.contains(id) {
-                return Err(Error::NotForSale);
-            }
-            let index = self.tokens_for_sale.iter().position(|token| *token == id).ok_or(Error::CannotFetchValue)?;
-            self.tokens_for_sale.remove(index);
-            self.prices.remove(id);
-            
-            Ok(())
+        /// Returns the balance of the owner.
+        ///
+        /// This represents the amount of unique tokens the owner has.
+        #[ink(message)]
+        pub fn balance_of(&self, owner: AccountId) -> u32 {
+            self.balance_of_or_zero(&owner)
         }
 
-        /// buy token for sale
-        #[ink(message, payable)]
-        pub fn buy_nft(&mut self, id: TokenId) -> Result<(), Error>{
-            let caller = self.env().caller();
-            if !self.exists(id) {
-                return Err(Error::TokenNotFound);
-            };
-            if self.is_owner_of(Some(caller), id) { // не продаем нфт ее же владельцу
-                return Err(Error::NotApproved);
-            };
-            if !self.prices.contains(id) {
-                return Err(Error::NotForSale);
-            }
-            let transfered_price = self.env().transferred_value();
-            let token_price = self.prices.get(id).unwrap();
-            if token_price > transfered_price {
-                return Err(Error::NotEnoughSent);
-            }
+        /// Returns the owner of the token.
+        #[ink(message)]
+        pub fn owner_of(&self, id: TokenId) -> Option<AccountId> {
+            self.token_owner.get(id)
+        }
 
-            let token_owner = self.owner_of(id).unwrap_or_default(); // хз почему мы можем не найти, но в этом кейсе мы платим на счет контракта хе-хе
-            let err = self.env().transfer(token_owner, token_price);
-            if err.is_err() {
-                return Err(Error::CannotMakeTransfer);
-            }
-            
-            self.transfer_token_from(&token_owner, &caller, id)?;
+        /// Returns the owner of the token or `TokenNotFound` if it does not exist.
+        #[ink(message)]
+        pub fn try_owner_of(&self, id: TokenId) -> Result<AccountId, Error> {
+            self.token_owner.get(id).ok_or(Error::TokenNotFound)
+        }
 
-            let index = self.tokens_for_sale.iter().position(|token| *token == id).ok_or(Error::CannotFetchValue)?;
-            self.tokens_for_sale.remove(index);
-            self.prices.remove(id);
-            
-            Ok(())
+        /// Returns true if `who` owns token `id`.
+        ///
+        /// Always false for the zero address and for not existing tokens.
+        #[ink(message)]
+        pub fn check_owner(&self, who: AccountId, id: TokenId) -> bool {
+            self.is_owner_of(Some(who), id)
         }
 
-        /// Transfer owned token.
+        /// Returns true if the contract implements interface `interface_id`, e.g. `INTERFACE_METADATA`.
         #[ink(message)]
-        pub fn get_nft_info(&self, id: TokenId) -> Result<NftData, Error> {
-            self.token_data.get(id).ok_or(Error::TokenNotFound)
+        pub fn supports_interface(&self, interface_id: [u8; 4]) -> bool {
+            SUPPORTED_INTERFACES.contains(&interface_id)
         }
 
-        /// Deletes an existing token. Only the owner can burn the token.
+        /// Returns true if token is held by the contract itself, e.g. listed in custody or wrapped.
         #[ink(message)]
-        pub fn burn(&mut self, id: TokenId) -> Result<(), Error> {
-            let caller = self.env().caller();
-            let Self {
-                token_owner,
-                owned_tokens,
-                owned_tokens_count,
-                all_tokens,
-                ..
-            } = self;
+        pub fn is_in_custody(&self, id: TokenId) -> bool {
+            self.owner_of(id) == Some(self.env().account_id())
+        }
 
-            let owner = token_owner.get(id).ok_or(Error::TokenNotFound)?;
-            if owner != caller {
-                return Err(Error::NotOwner);
-            };
+        /// Return all tokens held by the contract itself
+        #[ink(message)]
+        pub fn custodied_tokens(&self) -> Vec<TokenId> {
+            self.tokens_of_owner(self.env().account_id())
+        }
 
-            let count = owned_tokens_count
-                .get(caller)
-                .map(|c| c - 1)
-                .ok_or(Error::CannotFetchValue)?;
-            owned_tokens_count.insert(caller, &count);
-            
-            let mut tokens = owned_tokens
-                .get(caller)
-                .ok_or(Error::CannotFetchValue)?;
-            
-            let index = tokens.iter().position(|token| *token == id).ok_or(Error::CannotFetchValue)?;
-            tokens.remove(index);
-            owned_tokens.insert(caller, &tokens);
+        /// Return all tokens of owner
+        #[ink(message)]
+        pub fn tokens_of_owner(&self, owner: AccountId) -> Vec<TokenId> {
+            self.owned_tokens.get(owner).unwrap_or_default()
+        }
 
-            let index = all_tokens.iter().position(|token| *token == id).ok_or(Error::CannotFetchValue)?;
-            all_tokens.remove(index);
+        /// Return page of tokens of owner with their metadata, skipping tokens without metadata
+        #[ink(message)]
+        pub fn owned_with_metadata(&self, owner: AccountId, offset: u32, limit: u32) -> Vec<(TokenId, NftData)> {
+            self.tokens_of_owner(owner)
+                .into_iter()
+                .skip(offset as usize)
+                .take((limit as usize).min(MAX_BATCH_SIZE))
+                .filter_map(|id| self.metadata_of(id).map(|data| (id, data)))
+                .collect()
+        }
 
-            token_owner.remove(id);
+        /// Return all tokens
+        #[ink(message)]
+        pub fn get_all_tokens(&self) -> Vec<(TokenId, NftData)> {
+            self.all_tokens
+                .iter()
+                .filter_map(|id| self.metadata_of(*id).map(|data| (*id, data)))
+                .collect()
+        }
 
-            self.env().emit_event(Transfer {
+        /// Transfers the token from the caller to the given destination.
+        /// The caller must send at least the transfer fee, overpayment is refunded.
+        #[ink(message, payable)]
+        pub fn transfer(&mut self, destination: AccountId, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let excess = self.ensure_fee_paid(self.transfer_fee)?;
+            self.ensure_not_contract(&destination)?;
+            self.transfer_token_from(&caller, &destination, id)?;
+            self.pay_fee(&caller, self.transfer_fee, excess)
+        }
+
+        /// Transfers the token from the caller to the given destination with a reference memo.
+        /// The caller must send at least the transfer fee, overpayment is refunded.
+        #[ink(message, payable)]
+        pub fn transfer_with_memo(&mut self, to: AccountId, id: TokenId, memo: Vec<u8>) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if memo.len() > MAX_MEMO_LEN {
+                return Err(Error::MemoTooLong);
+            }
+            let excess = self.ensure_fee_paid(self.transfer_fee)?;
+            self.ensure_not_contract(&to)?;
+            self.transfer_token_from(&caller, &to, id)?;
+            self.env().emit_event(TransferWithMemo {
                 from: Some(caller),
-                to: Some(AccountId::from([0x0; 32])),
+                to: Some(to),
                 id,
+                memo,
             });
-
-            Ok(())
+            self.pay_fee(&caller, self.transfer_fee, excess)
         }
 
-        /// Transfers token `id` `from` the sender to the `to` `AccountId`.
-        fn transfer_token_from(
+        /// Transfer approved or owned token.
+        /// The caller must send at least the transfer fee, overpayment is refunded.
+        #[ink(message, payable)]
+        pub fn transfer_from(
             &mut self,
-            from: &AccountId,
-            to: &AccountId,
+            from: AccountId,
+            to: AccountId,
             id: TokenId,
         ) -> Result<(), Error> {
-            if !self.exists(id) {
-                return Err(Error::TokenNotFound);
-            };
-            if !self.is_owner_of(Some(*from), id) {
+            let caller = self.env().caller();
+            if !self.approved_or_owner(Some(caller), id) {
                 return Err(Error::NotApproved);
             };
-            self.remove_token_from(from, id)?;
-            self.add_token_to(to, id)?;
-            self.env().emit_event(Transfer {
-                from: Some(*from),
-                to: Some(*to),
-                id,
-            });
-            Ok(())
+            let excess = self.ensure_fee_paid(self.transfer_fee)?;
+            self.ensure_not_contract(&to)?;
+            self.transfer_token_from(&from, &to, id)?;
+            self.pay_fee(&caller, self.transfer_fee, excess)
         }
 
-        /// Removes token `id` from the owner.
-        fn remove_token_from(&mut self, from: &AccountId, id: TokenId) -> Result<(), Error> {
+        /// Transfer many approved or owned tokens of `from`.
+        ///
+        /// Every token is checked before moving any of them, so the batch is transferred
+        /// entirely or not at all. The caller must send the transfer fee for every token.
+        #[ink(message, payable)]
+        pub fn batch_transfer_from(
+            &mut self,
+            from: AccountId,
+            to: AccountId,
+            ids: Vec<TokenId>,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if ids.len() > MAX_BATCH_SIZE {
+                return Err(Error::NotAllowed);
+            }
+            let fee = self.transfer_fee.checked_mul(ids.len() as Balance).ok_or(Error::Overflow)?;
+            let excess = self.ensure_fee_paid(fee)?;
+            self.ensure_not_contract(&to)?;
+            for (i, id) in ids.iter().enumerate() {
+                if ids[..i].contains(id) {
+                    return Err(Error::NotAllowed);
+                }
+                if !self.approved_or_owner(Some(caller), *id) {
+                    return Err(Error::NotApproved);
+                }
+                self.ensure_can_transfer(&from, &to, *id)?;
+            }
+            if !self.batch_transfer_events {
+                for id in ids {
+                    self.transfer_token_from(&from, &to, id)?;
+                }
+            } else {
+                for id in &ids {
+                    self.move_token(&from, &to, *id)?;
+                }
+                self.env().emit_event(BatchTransfer {
+                    from: Some(from),
+                    to: Some(to),
+                    ids,
+                });
+            }
+            self.pay_fee(&caller, fee, excess)
+        }
+
+        /// Lock token in place for `staker` so it can not be transferred or burned until unlocked.
+        /// Only an approved operator or the owner of token can call it.
+        #[ink(message)]
+        pub fn stake_lock(&mut self, id: TokenId, staker: AccountId) -> Result<(), Error> {
+            let caller = self.env().caller();
             if !self.exists(id) {
                 return Err(Error::TokenNotFound);
             }
+            if !self.approved_or_owner(Some(caller), id) {
+                return Err(Error::NotApproved);
+            }
+            if self.staked_by.contains(id) {
+                return Err(Error::TokenStaked);
+            }
+            self.staked_by.insert(id, &staker);
+            self.stake_operators.insert(id, &caller);
+            Ok(())
+        }
 
-            let Self {
-                token_owner,
-                owned_tokens,
-                owned_tokens_count,
-                ..
-            } = self;
-
-            let count = owned_tokens_count
-                .get(from)
-                .map(|c| c - 1)
-                .ok_or(Error::CannotFetchValue)?;
-            owned_tokens_count.insert(from, &count);
-
-            let mut tokens = owned_tokens
-                .get(from)
-                .ok_or(Error::CannotFetchValue)?;
-            
-            let index = tokens.iter().position(|token| *token == id).ok_or(Error::CannotFetchValue)?;
-            tokens.remove(index);
-            owned_tokens.insert(from, &tokens);
-
-            token_owner.remove(id);
-
+        /// Unlock staked token. Only the account which locked the token can call it.
+        #[ink(message)]
+        pub fn stake_unlock(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let operator = self.stake_operators.get(id).ok_or(Error::NotAllowed)?;
+            if operator != caller {
+                return Err(Error::NotApproved);
+            }
+            self.staked_by.remove(id);
+            self.stake_operators.remove(id);
             Ok(())
         }
 
-        /// Adds the token `id` to the `to` AccountID.
-        fn add_token_to(&mut self, to: &AccountId, id: TokenId) -> Result<(), Error> {
-            let Self {
-                token_owner,
-                owned_tokens,
-                owned_tokens_count,
-                ..
-            } = self;
+        /// Returns the account which staked the token if it is locked.
+        #[ink(message)]
+        pub fn staker_of(&self, id: TokenId) -> Option<AccountId> {
+            self.staked_by.get(id)
+        }
 
-            if token_owner.contains(id) {
-                return Err(Error::TokenExists);
+        /// Transfer up to `count` tokens starting at index `from_index` of all tokens to `to`,
+        /// e.g. a contract of the next collection version. Returns the number of moved tokens.
+        /// Only the collection owner can call it.
+        ///
+        /// Every token is checked before moving any of them, so the range is moved entirely or not at all.
+        /// Listed tokens and tokens held by the contract, e.g. in auctions, are skipped, they can be
+        /// migrated once their sale ends.
+        #[ink(message)]
+        pub fn migrate_range(&mut self, to: AccountId, from_index: u32, count: u32) -> Result<u32, Error> {
+            self.ensure_owner()?;
+            self.ensure_not_contract(&to)?;
+            let tokens: Vec<(AccountId, TokenId)> = self
+                .all_tokens
+                .iter()
+                .skip(from_index as usize)
+                .take((count as usize).min(MAX_BATCH_SIZE))
+                .filter(|id| !self.is_in_custody(**id) && !self.prices.contains(*id))
+                .map(|id| (self.owner_of(*id).unwrap_or_default(), *id))
+                .collect();
+            for (from, id) in tokens.iter() {
+                self.ensure_can_transfer(from, &to, *id)?;
+            }
+            for (from, id) in tokens.iter() {
+                self.transfer_token_from(from, &to, *id)?;
             }
+            Ok(tokens.len() as u32)
+        }
 
-            if *to == AccountId::from([0x0; 32]) {
-                return Err(Error::NotAllowed);
-            };
+        /// Returns the approved account ID for this token if any.
+        #[ink(message)]
+        pub fn get_approved(&self, id: TokenId) -> Option<AccountId> {
+            self.token_approvals.get(id)
+        }
 
-            let count = owned_tokens_count.get(to).map(|c| c + 1).unwrap_or(1);
-            owned_tokens_count.insert(to, &count);
+        /// Returns `true` if the operator is approved by the owner.
+        #[ink(message)]
+        pub fn is_approved_for_all(&self, owner: AccountId, operator: AccountId) -> bool {
+            self.approved_for_all(owner, operator)
+        }
 
-            let mut tokens = owned_tokens
-                .get(to)
-                .unwrap_or_default();
-            tokens.push(id);
-            owned_tokens.insert(to, &tokens);
+        /// Returns operators approved for all tokens of `owner`.
+        #[ink(message)]
+        pub fn approved_operators_of(&self, owner: AccountId) -> Vec<AccountId> {
+            self.operators_list.get(owner).unwrap_or_default()
+        }
 
-            token_owner.insert(id, to);
+        /// Returns `true` if `spender` owns token `id`, is approved for it or is an operator of its owner.
+        /// Uses the same check which guards transfers.
+        #[ink(message)]
+        pub fn is_approved_or_owner(&self, spender: AccountId, id: TokenId) -> bool {
+            self.approved_or_owner(Some(spender), id)
+        }
 
+        /// Approves or disapproves the operator for all tokens of the caller.
+        #[ink(message)]
+        pub fn set_approval_for_all(&mut self, to: AccountId, approved: bool) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if to == caller {
+                return Err(Error::NotAllowed);
+            }
+            let mut operators = self.operators_list.get(caller).unwrap_or_default();
+            if approved {
+                if !self.operator_approvals.contains((&caller, &to)) {
+                    operators.push(to);
+                }
+                self.operator_approvals.insert((&caller, &to), &());
+            } else {
+                operators.retain(|operator| *operator != to);
+                self.operator_approvals.remove((&caller, &to));
+            }
+            if operators.is_empty() {
+                self.operators_list.remove(caller);
+            } else {
+                self.operators_list.insert(caller, &operators);
+            }
+            self.env().emit_event(ApprovalForAll {
+                owner: caller,
+                operator: to,
+                approved,
+            });
             Ok(())
         }
 
-        // Returns the total number of tokens from an account.
-        fn balance_of_or_zero(&self, of: &AccountId) -> u32 {
-            self.owned_tokens_count.get(of).unwrap_or(0)
+        /// Approves the account to transfer the specified token on behalf of the caller.
+        #[ink(message)]
+        pub fn approve(&mut self, to: AccountId, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.approve_for(&caller, &to, id)
         }
 
-        /// Returns true if the `AccountId` `from` is the owner of token `id`
-        fn is_owner_of(&self, from: Option<AccountId>, id: TokenId) -> bool {
-            let owner = self.owner_of(id);
-            from != Some(AccountId::from([0x0; 32])) && (from == owner)
+        /// Approves the account to transfer each of the specified tokens owned by the caller.
+        /// Nothing is approved if any token cannot be approved.
+        #[ink(message)]
+        pub fn batch_approve(&mut self, to: AccountId, ids: Vec<TokenId>) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if ids.len() > MAX_BATCH_SIZE {
+                return Err(Error::NotAllowed);
+            }
+            for id in ids.iter() {
+                if !self.is_owner_of(Some(caller), *id) {
+                    return Err(Error::NotOwner);
+                }
+                self.ensure_can_approve(&caller, &to, *id)?;
+            }
+            for id in ids {
+                self.approve_for(&caller, &to, id)?;
+            }
+            Ok(())
         }
 
-        /// Returns true if token `id` exists or false if it does not.
-        fn exists(&self, id: TokenId) -> bool {
-            self.token_owner.contains(id)
+        /// Returns the nonce signed into the next permit of `owner`.
+        #[ink(message)]
+        pub fn nonce_of(&self, owner: AccountId) -> u64 {
+            self.nonces.get(owner).unwrap_or(0)
         }
-    }
 
-    /// Unit tests
-    #[cfg(test)]
-    mod tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
+        /// Approves `spender` for token `id` on behalf of `owner` with a signature of `owner`,
+        /// so a relayer can submit the approval.
+        ///
+        /// `signature` is an ECDSA signature of `permit_hash` made by the key of `owner`
+        /// and valid until block `deadline`.
+        #[ink(message)]
+        pub fn permit(
+            &mut self,
+            owner: AccountId,
+            spender: AccountId,
+            id: TokenId,
+            deadline: BlockNumber,
+            signature: [u8; 65],
+        ) -> Result<(), Error> {
+            if self.env().block_number() > deadline {
+                return Err(Error::PermitExpired);
+            }
+            let signer = self.recover_signer(&signature, &self.permit_hash(owner, spender, id, deadline))?;
+            if signer != owner {
+                return Err(Error::InvalidSignature);
+            }
 
-        #[ink_lang::test]
-        fn mint_works() {
-            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
-            // Create a new contract instance.
+            self.approve_for(&owner, &spender, id)?;
+            self.nonces.insert(owner, &(self.nonce_of(owner) + 1));
+            Ok(())
+        }
+
+        /// Returns the hash `owner` signs to permit `spender` for token `id` with the current nonce.
+        #[ink(message)]
+        pub fn permit_hash(&self, owner: AccountId, spender: AccountId, id: TokenId, deadline: BlockNumber) -> [u8; 32] {
+            let message = (self.env().account_id(), owner, spender, id, deadline, self.nonce_of(owner));
+            self.env().hash_encoded::<ink_env::hash::Blake2x256, _>(&message)
+        }
+
+        /// Mints token of `voucher` signed by its creator to the caller and pays the creator.
+        ///
+        /// The creator has to be a minter and the caller has to pay at least `min_price` of the voucher.
+        #[ink(message, payable)]
+        pub fn redeem_voucher(&mut self, voucher: MintVoucher, signature: [u8; 65]) -> Result<TokenId, Error> {
+            let caller = self.env().caller();
+            let signer = self.recover_signer(&signature, &self.hash_voucher(&voucher))?;
+            if signer != voucher.creator {
+                return Err(Error::InvalidSignature);
+            }
+            if !self.minters.contains(voucher.creator) {
+                return Err(Error::NotAllowed);
+            }
+            if self.redeemed_vouchers.contains((voucher.creator, voucher.nonce)) {
+                return Err(Error::VoucherRedeemed);
+            }
+            let payment = self.env().transferred_value();
+            if payment < voucher.min_price {
+                return Err(Error::NotEnoughSent);
+            }
+            self.ensure_can_mint(1)?;
+            let id = match voucher.id {
+                Some(id) if self.exists(id) => return Err(Error::TokenExists),
+                Some(id) => id,
+                None => self.take_next_token_id()?,
+            };
+
+            self.mint_token_to(&caller, id, &voucher.metadata)?;
+            self.creators.insert(id, &voucher.creator);
+            self.redeemed_vouchers.insert((voucher.creator, voucher.nonce), &());
+            self.pay(voucher.creator, payment)?;
+            Ok(id)
+        }
+
+        /// Returns the hash the creator signs to issue `voucher`.
+        #[ink(message)]
+        pub fn voucher_hash(&self, voucher: MintVoucher) -> [u8; 32] {
+            self.hash_voucher(&voucher)
+        }
+
+        /// Creates a new token.
+        #[ink(message, payable)]
+        pub fn mint(&mut self, id: TokenId, data: NftData) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let excess = self.ensure_fee_paid(self.mint_fee)?;
+            self.ensure_can_mint(1)?;
+            self.ensure_below_mint_limit(&caller)?;
+            self.mint_token_to(&caller, id, &data)?;
+            self.record_public_mint(&caller);
+            self.collect_mint_fee(&caller, excess)
+        }
+
+        /// Stores metadata shared by many tokens, returns id of the template. Only minters can call it.
+        #[ink(message)]
+        pub fn create_template(&mut self, data: NftData) -> Result<u32, Error> {
+            if !self.minters.contains(self.env().caller()) {
+                return Err(Error::NotAllowed);
+            }
+            let template_id = self.next_template_id;
+            self.next_template_id = template_id.checked_add(1).ok_or(Error::CannotInsert)?;
+            self.templates.insert(template_id, &data);
+            Ok(template_id)
+        }
+
+        /// Returns metadata of template.
+        #[ink(message)]
+        pub fn get_template(&self, template_id: u32) -> Option<NftData> {
+            self.templates.get(template_id)
+        }
+
+        /// Creates a new token sharing metadata of template `template_id` instead of storing its own.
+        #[ink(message, payable)]
+        pub fn mint_from_template(&mut self, id: TokenId, template_id: u32) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let excess = self.ensure_fee_paid(self.mint_fee)?;
+            if !self.templates.contains(template_id) {
+                return Err(Error::TemplateNotFound);
+            }
+            self.ensure_can_mint(1)?;
+            self.ensure_below_mint_limit(&caller)?;
+            self.mint_template_to(&caller, id, template_id)?;
+            self.record_public_mint(&caller);
+            self.collect_mint_fee(&caller, excess)
+        }
+
+        /// Creates a new token with metadata stored on IPFS under `cid` multihash bytes.
+        #[ink(message, payable)]
+        pub fn mint_with_cid(&mut self, id: TokenId, cid: Vec<u8>) -> Result<(), Error> {
+            let cid = Cid::from_bytes(&cid)?;
+            self.mint(id, NftData { poebat: None })?;
+            self.token_cids.insert(id, &cid);
+            Ok(())
+        }
+
+        /// Returns URI of token metadata, `ipfs://` URI if token was minted with a CID,
+        /// base URI followed by token id if token has no own URI.
+        ///
+        /// While metadata is hidden, returns the placeholder URI for tokens outside of
+        /// revealed ranges and base URI of the range followed by token id for the others.
+        #[ink(message)]
+        pub fn token_uri(&self, id: TokenId) -> Option<String> {
+            if let Some(placeholder_uri) = &self.placeholder_uri {
+                if !self.exists(id) {
+                    return None;
+                }
+                return match self.revealed_ranges.iter().find(|(from, to, _)| (*from..=*to).contains(&id)) {
+                    Some((_, _, base_uri)) => Some(ink_prelude::format!("{}{}", base_uri, id)),
+                    None => Some(placeholder_uri.clone()),
+                };
+            }
+            match self.token_cids.get(id) {
+                Some(cid) => Some(cid.to_uri()),
+                None => {
+                    let own_uri = self.metadata_of(id)?.poebat;
+                    own_uri.or_else(|| Some(ink_prelude::format!("{}{}", self.base_uri.as_ref()?, id)))
+                }
+            }
+        }
+
+        /// Returns URI of tokens not revealed yet, none if metadata is not hidden.
+        #[ink(message)]
+        pub fn placeholder_uri(&self) -> Option<String> {
+            self.placeholder_uri.clone()
+        }
+
+        /// Hide metadata of tokens outside of revealed ranges behind `placeholder_uri`,
+        /// `None` shows metadata of all tokens. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_placeholder_uri(&mut self, placeholder_uri: Option<String>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.ensure_collection_not_frozen()?;
+            self.placeholder_uri = placeholder_uri;
+            Ok(())
+        }
+
+        /// Reveal metadata of tokens `from_id..=to_id` under `base_uri` followed by token id.
+        /// Only the collection owner can call it.
+        #[ink(message)]
+        pub fn reveal_range(&mut self, from_id: TokenId, to_id: TokenId, base_uri: String) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.ensure_collection_not_frozen()?;
+            if from_id > to_id || self.revealed_ranges.len() >= MAX_REVEALED_RANGES {
+                return Err(Error::NotAllowed);
+            }
+            if self.revealed_ranges.iter().any(|(from, to, _)| from_id <= *to && *from <= to_id) {
+                return Err(Error::NotAllowed);
+            }
+            self.revealed_ranges.push((from_id, to_id, base_uri));
+            Ok(())
+        }
+
+        /// Returns revealed id ranges as (from id, to id inclusive, base URI).
+        #[ink(message)]
+        pub fn revealed_ranges(&self) -> Vec<(TokenId, TokenId, String)> {
+            self.revealed_ranges.clone()
+        }
+
+        /// Set URI of token metadata. Only the creator of token can call it until metadata is frozen.
+        #[ink(message)]
+        pub fn set_token_uri(&mut self, id: TokenId, uri: String) -> Result<(), Error> {
+            self.ensure_can_edit_metadata(id)?;
+            let mut data = self.metadata_of(id).ok_or(Error::CannotFetchValue)?;
+            data.poebat = Some(uri);
+            self.token_data.insert(id, &data);
+            self.token_cids.remove(id);
+            Ok(())
+        }
+
+        /// Replace metadata of token, e.g. to fix its URI.
+        /// Only the creator of token or a minter can call it until metadata is frozen.
+        #[ink(message)]
+        pub fn update_metadata(&mut self, id: TokenId, data: NftData) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.minters.contains(caller) {
+                self.ensure_can_edit_metadata(id)?;
+            } else if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            } else if self.is_metadata_frozen(id) {
+                return Err(Error::MetadataFrozen);
+            }
+            self.token_data.insert(id, &data);
+            self.token_cids.remove(id);
+            self.env().emit_event(MetadataUpdated { id });
+            Ok(())
+        }
+
+        /// Returns attribute `key` of token.
+        #[ink(message)]
+        pub fn get_attribute(&self, id: TokenId, key: String) -> Option<String> {
+            self.attributes.get((id, key))
+        }
+
+        /// Set attribute `key` of token. Only the creator of token can call it until metadata is frozen.
+        #[ink(message)]
+        pub fn set_attribute(&mut self, id: TokenId, key: String, value: String) -> Result<(), Error> {
+            self.ensure_can_edit_metadata(id)?;
+            match self.attributes.get((id, key.clone())) {
+                Some(old_value) => self.unindex_attribute(id, key.clone(), old_value),
+                None => {
+                    let mut keys = self.attribute_keys.get(id).unwrap_or_default();
+                    keys.push(key.clone());
+                    self.attribute_keys.insert(id, &keys);
+                }
+            }
+            let mut ids = self.attribute_index.get((key.clone(), value.clone())).unwrap_or_default();
+            ids.push(id);
+            self.attribute_index.insert((key.clone(), value.clone()), &ids);
+            self.attributes.insert((id, key), &value);
+            Ok(())
+        }
+
+        /// Returns tokens with attribute `key` set to `value`.
+        /// Only first `MAX_BATCH_SIZE` tokens are returned, see `tokens_with_attribute_paged`.
+        #[ink(message)]
+        pub fn tokens_with_attribute(&self, key: String, value: String) -> Vec<TokenId> {
+            self.tokens_with_attribute_paged(key, value, 0, MAX_BATCH_SIZE as u32)
+        }
+
+        /// Returns page of tokens with attribute `key` set to `value`, at most `MAX_BATCH_SIZE` tokens.
+        #[ink(message)]
+        pub fn tokens_with_attribute_paged(&self, key: String, value: String, offset: u32, limit: u32) -> Vec<TokenId> {
+            self.attribute_index
+                .get((key, value))
+                .unwrap_or_default()
+                .into_iter()
+                .skip(offset as usize)
+                .take((limit as usize).min(MAX_BATCH_SIZE))
+                .collect()
+        }
+
+        /// Returns true if metadata of token cannot be changed anymore.
+        #[ink(message)]
+        pub fn is_metadata_frozen(&self, id: TokenId) -> bool {
+            self.metadata_frozen || self.frozen_metadata.contains(id)
+        }
+
+        /// Returns true if metadata of the whole collection cannot be changed anymore.
+        #[ink(message)]
+        pub fn is_collection_frozen(&self) -> bool {
+            self.metadata_frozen
+        }
+
+        /// Permanently lock metadata of all tokens, base URI and reveal settings.
+        /// Only the collection owner can call it.
+        #[ink(message)]
+        pub fn freeze_all_metadata(&mut self) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.metadata_frozen = true;
+            Ok(())
+        }
+
+        /// Permanently lock metadata of token. Only the owner or the creator of token can call it.
+        #[ink(message)]
+        pub fn freeze_metadata(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            }
+            if !self.is_owner_of(Some(caller), id) && self.creators.get(id) != Some(caller) {
+                return Err(Error::NotAllowed);
+            }
+            self.frozen_metadata.insert(id, &());
+            Ok(())
+        }
+
+        /// Creates a new token with royalty paid to `royalty_recipient` on every sale.
+        /// Nothing is minted if royalty is invalid.
+        #[ink(message, payable)]
+        pub fn mint_with_royalty(
+            &mut self,
+            id: TokenId,
+            data: NftData,
+            royalty_recipient: AccountId,
+            royalty_bps: u16,
+        ) -> Result<(), Error> {
+            let royalty = ink_prelude::vec![(royalty_recipient, royalty_bps)];
+            ensure_valid_royalty(&royalty)?;
+            self.ensure_royalty_within_cap(&royalty)?;
+            self.mint(id, data)?;
+            self.royalties.insert(id, &royalty);
+            Ok(())
+        }
+
+        /// Creates a new token with the next sequential id.
+        #[ink(message, payable)]
+        pub fn mint_next(&mut self, data: NftData) -> Result<TokenId, Error> {
+            let caller = self.env().caller();
+            let excess = self.ensure_fee_paid(self.mint_fee)?;
+            self.ensure_can_mint(1)?;
+            self.ensure_below_mint_limit(&caller)?;
+            let id = self.take_next_token_id()?;
+            self.mint_token_to(&caller, id, &data)?;
+            self.record_public_mint(&caller);
+            self.collect_mint_fee(&caller, excess)?;
+            Ok(id)
+        }
+
+        /// Mints `count` sequential tokens to `to` before a public sale. Only minters can call it.
+        #[ink(message)]
+        pub fn reserve_mint(&mut self, to: AccountId, count: u32) -> Result<Vec<TokenId>, Error> {
+            let caller = self.env().caller();
+            if !self.minters.contains(caller) {
+                return Err(Error::NotAllowed);
+            }
+            if to == AccountId::from([0x0; 32]) || to == self.env().account_id() {
+                return Err(Error::NotAllowed);
+            }
+            self.ensure_can_mint(count)?;
+
+            let mut ids = Vec::new();
+            for _ in 0..count {
+                let id = self.take_next_token_id()?;
+                if self.batch_transfer_events {
+                    self.create_token(&to, id, &NftData { poebat: None })?;
+                } else {
+                    self.mint_token_to(&to, id, &NftData { poebat: None })?;
+                }
+                ids.push(id);
+            }
+            if self.batch_transfer_events {
+                self.env().emit_event(BatchTransfer {
+                    from: Some(AccountId::from([0x0; 32])),
+                    to: Some(to),
+                    ids: ids.clone(),
+                });
+            }
+            Ok(ids)
+        }
+
+        /// Creates token `id` owned by `to`. Only minters can call it.
+        #[ink(message)]
+        pub fn mint_to(&mut self, to: AccountId, id: TokenId, data: NftData) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.minters.contains(caller) {
+                return Err(Error::NotAllowed);
+            }
+            if to == AccountId::from([0x0; 32]) || to == self.env().account_id() {
+                return Err(Error::NotAllowed);
+            }
+            self.ensure_can_mint(1)?;
+            self.mint_token_to(&to, id, &data)
+        }
+
+        /// Returns the number of existing tokens.
+        #[ink(message)]
+        pub fn total_supply(&self) -> u32 {
+            self.all_tokens.len() as u32
+        }
+
+        /// Returns true if minting is closed forever.
+        #[ink(message)]
+        pub fn supply_locked(&self) -> bool {
+            self.supply_locked
+        }
+
+        /// Close minting forever, it can not be reopened. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn lock_supply(&mut self) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.supply_locked = true;
+            Ok(())
+        }
+
+        /// Diagnostic check for tests and audits: returns true if balances of `owners` sum to
+        /// the total supply, their token lists match their balances and owners, and every token
+        /// has an owner. `owners` has to list every holder once.
+        #[ink(message)]
+        pub fn verify_invariants(&self, owners: Vec<AccountId>) -> bool {
+            let mut total: u32 = 0;
+            for (i, owner) in owners.iter().enumerate() {
+                if owners[..i].contains(owner) {
+                    return false;
+                }
+                let tokens = self.tokens_of_owner(*owner);
+                let balance = self.balance_of(*owner);
+                if tokens.len() as u32 != balance || tokens.iter().any(|id| self.owner_of(*id) != Some(*owner)) {
+                    return false;
+                }
+                total = total.saturating_add(balance);
+            }
+            total == self.total_supply() && self.all_tokens.iter().all(|id| self.exists(*id))
+        }
+
+        /// Returns id which the next sequential mint assigns, skipping explicitly minted ids.
+        #[ink(message)]
+        pub fn next_token_id(&self) -> TokenId {
+            self.peek_next_token_id().unwrap_or(self.next_token_id)
+        }
+
+        /// Returns max number of existing tokens.
+        #[ink(message)]
+        pub fn max_supply(&self) -> Option<u32> {
+            self.max_supply
+        }
+
+        /// Set max number of existing tokens, `None` removes the cap. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_max_supply(&mut self, max_supply: Option<u32>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.max_supply = max_supply;
+            Ok(())
+        }
+
+        /// Returns max number of tokens one account can mint.
+        #[ink(message)]
+        pub fn max_per_account(&self) -> Option<u32> {
+            self.max_per_account
+        }
+
+        /// Set max number of tokens one account can mint, `None` removes the limit.
+        /// Only the collection owner can call it.
+        ///
+        /// The limit counts lifetime mints via `mint`/`mint_next`, so selling or burning
+        /// tokens does not allow minting more. `reserve_mint` is not limited.
+        #[ink(message)]
+        pub fn set_max_per_account(&mut self, max_per_account: Option<u32>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.max_per_account = max_per_account;
+            Ok(())
+        }
+
+        /// Returns number of tokens minted by `account` via `mint`/`mint_next`.
+        #[ink(message)]
+        pub fn minted_count_of(&self, account: AccountId) -> u32 {
+            self.minted_count.get(account).unwrap_or(0)
+        }
+
+        /// Re-adds existing tokens missing in the enumeration of all tokens.
+        /// Only the collection owner can call it, ids already present are skipped.
+        #[ink(message)]
+        pub fn rebuild_index(&mut self, ids: Vec<TokenId>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            for id in ids {
+                if self.exists(id) && !self.all_tokens.contains(&id) {
+                    self.all_tokens.push(id);
+                }
+            }
+            Ok(())
+        }
+
+        /// Returns true if transfers of blacklisted accounts are blocked.
+        #[ink(message)]
+        pub fn blacklist_enabled(&self) -> bool {
+            self.blacklist_enabled
+        }
+
+        /// Returns true if `account` is blacklisted.
+        #[ink(message)]
+        pub fn is_blacklisted(&self, account: AccountId) -> bool {
+            self.blacklist.contains(account)
+        }
+
+        /// Block transfers from and to `account`. Only the collection owner can call it
+        /// and only if the contract was created with blacklist enabled.
+        #[ink(message)]
+        pub fn add_to_blacklist(&mut self, account: AccountId) -> Result<(), Error> {
+            self.ensure_owner()?;
+            if !self.blacklist_enabled {
+                return Err(Error::NotAllowed);
+            }
+            self.blacklist.insert(account, &());
+            Ok(())
+        }
+
+        /// Unblock transfers from and to `account`. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn remove_from_blacklist(&mut self, account: AccountId) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.blacklist.remove(account);
+            Ok(())
+        }
+
+        /// Returns min number of blocks between transfers of the same token.
+        #[ink(message)]
+        pub fn cooldown_blocks(&self) -> Option<BlockNumber> {
+            self.cooldown_blocks
+        }
+
+        /// Set min number of blocks between transfers of the same token, `None` disables the check.
+        /// Moves into and out of custody of the contract are not limited. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_cooldown_blocks(&mut self, cooldown_blocks: Option<BlockNumber>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.cooldown_blocks = cooldown_blocks;
+            Ok(())
+        }
+
+        /// Returns marketplace fee kept from every sale in basis points.
+        #[ink(message)]
+        pub fn fee_bps(&self) -> u16 {
+            self.fee_bps
+        }
+
+        /// Set marketplace fee kept from every sale in basis points. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_fee_bps(&mut self, fee_bps: u16) -> Result<(), Error> {
+            self.ensure_owner()?;
+            if fee_bps > MAX_BPS {
+                return Err(Error::NotAllowed);
+            }
+            self.fee_bps = fee_bps;
+            Ok(())
+        }
+
+        /// Returns fee paid for every token minted via `mint`/`mint_next`.
+        #[ink(message)]
+        pub fn mint_fee(&self) -> Balance {
+            self.mint_fee
+        }
+
+        /// Set fee paid for every token minted via `mint`/`mint_next`. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_mint_fee(&mut self, mint_fee: Balance) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.mint_fee = mint_fee;
+            Ok(())
+        }
+
+        /// Returns fee paid for every token burned via `burn` or `burn_range`.
+        #[ink(message)]
+        pub fn burn_fee(&self) -> Balance {
+            self.burn_fee
+        }
+
+        /// Set fee paid for every token burned via `burn` or `burn_range`. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_burn_fee(&mut self, burn_fee: Balance) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.burn_fee = burn_fee;
+            Ok(())
+        }
+
+        /// Returns recipients splitting mint fees with their basis points.
+        #[ink(message)]
+        pub fn revenue_shares(&self) -> Vec<(AccountId, u16)> {
+            self.revenue_shares.clone()
+        }
+
+        /// Split mint fees between `shares` summing to `MAX_BPS`, or pay them to the fee recipient
+        /// if `shares` is empty. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_revenue_shares(&mut self, shares: Vec<(AccountId, u16)>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            let total: u32 = shares.iter().map(|(_, bps)| u32::from(*bps)).sum();
+            if shares.len() > MAX_REVENUE_SHARES || (!shares.is_empty() && total != u32::from(MAX_BPS)) {
+                return Err(Error::InvalidShares);
+            }
+            self.revenue_shares = shares;
+            Ok(())
+        }
+
+        /// Returns amount credited to `account` which it can withdraw.
+        #[ink(message)]
+        pub fn pending_withdrawal_of(&self, account: AccountId) -> Balance {
+            self.pending_withdrawals.get(account).unwrap_or(0)
+        }
+
+        /// Pay amount credited to the caller, returns the paid amount.
+        #[ink(message)]
+        pub fn withdraw(&mut self) -> Result<Balance, Error> {
+            let caller = self.env().caller();
+            let amount = self.pending_withdrawal_of(caller);
+            if amount == 0 {
+                return Err(Error::NotAllowed);
+            }
+            self.pay(caller, amount)?;
+            self.pending_withdrawals.remove(caller);
+            Ok(amount)
+        }
+
+        /// Returns fee paid for every token transferred via `transfer`/`transfer_from`.
+        #[ink(message)]
+        pub fn transfer_fee(&self) -> Balance {
+            self.transfer_fee
+        }
+
+        /// Set fee paid for every token transferred via `transfer`/`transfer_from`, minting and burning
+        /// are exempt. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_transfer_fee(&mut self, transfer_fee: Balance) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.transfer_fee = transfer_fee;
+            Ok(())
+        }
+
+        /// Returns account receiving mint fees.
+        #[ink(message)]
+        pub fn fee_recipient(&self) -> AccountId {
+            self.fee_recipient
+        }
+
+        /// Set account receiving mint fees. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_fee_recipient(&mut self, fee_recipient: AccountId) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.fee_recipient = fee_recipient;
+            Ok(())
+        }
+
+        /// Returns marketplace fees kept in native balance and not withdrawn yet.
+        #[ink(message)]
+        pub fn accrued_fees(&self) -> Balance {
+            self.accrued_fees
+        }
+
+        /// Returns marketplace fees kept in the payment token and not withdrawn yet.
+        #[ink(message)]
+        pub fn accrued_token_fees(&self) -> Balance {
+            self.accrued_token_fees
+        }
+
+        /// Returns contract notified of every token transfer.
+        #[ink(message)]
+        pub fn transfer_hook(&self) -> Option<AccountId> {
+            self.transfer_hook
+        }
+
+        /// Set contract notified with `on_transfer(from, to, id)` of every token transfer, `None` to
+        /// stop notifying. Failure of the hook does not revert the transfer.
+        /// Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_transfer_hook(&mut self, transfer_hook: Option<AccountId>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.transfer_hook = transfer_hook;
+            Ok(())
+        }
+
+        /// Returns gas available to the transfer hook call.
+        #[ink(message)]
+        pub fn transfer_hook_gas_limit(&self) -> u64 {
+            self.transfer_hook_gas_limit
+        }
+
+        /// Set gas available to the transfer hook call, from 1 up to `MAX_TRANSFER_HOOK_GAS_LIMIT`,
+        /// so a hook running out of it fails alone while the transfer keeps the rest of its gas.
+        /// Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_transfer_hook_gas_limit(&mut self, gas_limit: u64) -> Result<(), Error> {
+            self.ensure_owner()?;
+            // zero would forward all remaining gas
+            if gas_limit == 0 || gas_limit > MAX_TRANSFER_HOOK_GAS_LIMIT {
+                return Err(Error::NotAllowed);
+            }
+            self.transfer_hook_gas_limit = gas_limit;
+            Ok(())
+        }
+
+        /// Returns PSP22 token paying for `buy_nft` sales, none for native balance.
+        #[ink(message)]
+        pub fn payment_token(&self) -> Option<AccountId> {
+            self.payment_token
+        }
+
+        /// Set PSP22 token paying for `buy_nft` sales, `None` for native balance.
+        /// Fees kept in the current payment token have to be withdrawn first.
+        /// Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_payment_token(&mut self, payment_token: Option<AccountId>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            if self.accrued_token_fees > 0 {
+                return Err(Error::NotAllowed);
+            }
+            self.payment_token = payment_token;
+            Ok(())
+        }
+
+        /// Transfer marketplace fees kept in native balance to `to`. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn withdraw_fees(&mut self, to: AccountId) -> Result<Balance, Error> {
+            self.ensure_owner()?;
+            let amount = self.accrued_fees;
+            self.pay(to, amount)?;
+            self.accrued_fees = 0;
+            Ok(amount)
+        }
+
+        /// Transfer marketplace fees kept in the payment token to `to`. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn withdraw_token_fees(&mut self, to: AccountId) -> Result<Balance, Error> {
+            self.ensure_owner()?;
+            let amount = self.accrued_token_fees;
+            self.pay_in(self.payment_token, to, amount)?;
+            self.accrued_token_fees = 0;
+            Ok(amount)
+        }
+
+        /// Returns true if `account` is allowed to mint reserved tokens.
+        #[ink(message)]
+        pub fn is_minter(&self, account: AccountId) -> bool {
+            self.minters.contains(account)
+        }
+
+        /// Grant minter rights. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn add_minter(&mut self, account: AccountId) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.minters.insert(account, &());
+            Ok(())
+        }
+
+        /// Revoke minter rights. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn remove_minter(&mut self, account: AccountId) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.minters.remove(account);
+            self.env().emit_event(MinterRemoved { account });
+            Ok(())
+        }
+
+        /// Drop minter rights of the caller, e.g. to prove the supply is final after a drop.
+        #[ink(message)]
+        pub fn renounce_minter(&mut self) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.minters.contains(caller) {
+                return Err(Error::NotAllowed);
+            }
+            self.minters.remove(caller);
+            self.env().emit_event(MinterRemoved { account: caller });
+            Ok(())
+        }
+        
+        /// add token id for sale 
+        #[ink(message)]
+        pub fn publish_for_sale(&mut self, id: TokenId, price: Balance) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.ensure_can_list(&caller, id, price)?;
+            // the contract moves the token on sale, so it must be approved for it
+            if !self.approved_or_owner(Some(self.env().account_id()), id) {
+                return Err(Error::MarketplaceNotApproved);
+            }
+            
+            self.add_listing(&caller, id, price)?;
+            
+            Ok(())
+        }
+
+        /// add many tokens for sale each at its own price, nothing is listed if any token can not be
+        #[ink(message)]
+        pub fn publish_batch_for_sale(&mut self, listings: Vec<(TokenId, Balance)>) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if listings.len() > MAX_BATCH_SIZE {
+                return Err(Error::NotAllowed);
+            }
+            for (i, (id, price)) in listings.iter().enumerate() {
+                if listings[..i].iter().any(|(listed, _)| listed == id) {
+                    return Err(Error::AlreadyForSale);
+                }
+                self.ensure_can_list(&caller, *id, *price)?;
+                if !self.approved_or_owner(Some(self.env().account_id()), *id) {
+                    return Err(Error::MarketplaceNotApproved);
+                }
+            }
+
+            for (id, price) in listings {
+                self.add_listing(&caller, id, price)?;
+            }
+            Ok(())
+        }
+
+        /// add token id for sale to `buyer` only, hidden from public listings, events and floor price
+        #[ink(message)]
+        pub fn publish_for_sale_private(&mut self, id: TokenId, price: Balance, buyer: AccountId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.ensure_can_list(&caller, id, price)?;
+            if !self.approved_or_owner(Some(self.env().account_id()), id) {
+                return Err(Error::MarketplaceNotApproved);
+            }
+            self.store_listing(&caller, id, price)?;
+            self.private_buyers.insert(id, &buyer);
+            self.private_listings_count = self.private_listings_count.checked_add(1).ok_or(Error::Overflow)?;
+            Ok(())
+        }
+
+        /// get the only account allowed to buy token published for sale privately
+        #[ink(message)]
+        pub fn private_buyer_of(&self, id: TokenId) -> Option<AccountId> {
+            self.private_buyers.get(id)
+        }
+
+        /// approve the contract for token id and add it for sale in one call
+        #[ink(message)]
+        pub fn approve_and_list(&mut self, id: TokenId, price: Balance) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let contract = self.env().account_id();
+            self.ensure_can_list(&caller, id, price)?;
+            if self.get_approved(id) != Some(contract) {
+                self.approve_for(&caller, &contract, id)?;
+            }
+
+            self.add_listing(&caller, id, price)?;
+
+            Ok(())
+        }
+
+        /// add token id for sale holding the token in the contract until it is sold or removed from sale
+        #[ink(message)]
+        pub fn publish_for_sale_custodial(&mut self, id: TokenId, price: Balance) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.ensure_can_list(&caller, id, price)?;
+
+            let contract = self.env().account_id();
+            self.transfer_token_from(&caller, &contract, id)?;
+            self.add_listing(&caller, id, price)?;
+            self.custodial_listings.insert(id, &());
+
+            Ok(())
+        }
+
+        /// get price of token which published for sale
+        #[ink(message)]
+        pub fn price_of(&self, id: TokenId) -> Result<Balance, Error> {
+            self.prices.get(id).ok_or(Error::NotForSale)
+        }
+
+        /// get account which published token for sale
+        #[ink(message)]
+        pub fn seller_of(&self, id: TokenId) -> Option<AccountId> {
+            self.sellers.get(id)
+        }
+
+        /// Returns the lowest price of tokens published for sale publicly, or `None` if there are none.
+        #[ink(message)]
+        pub fn floor_listing_price(&self) -> Option<Balance> {
+            self.lowest_listing_price
+        }
+
+        /// change price of token which published for sale
+        #[ink(message)]
+        pub fn update_price(&mut self, id: TokenId, price: Balance) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            if !self.is_seller_of(&caller, id) {
+                return Err(Error::NotApproved);
+            };
+            if !self.prices.contains(id) {
+                return Err(Error::NotForSale);
+            }
+            self.ensure_valid_price(price)?;
+
+            let old_price = self.prices.get(id);
+            self.prices.insert(id, &price);
+            if self.private_buyers.contains(id) {
+                return Ok(());
+            }
+            if self.lowest_listing_price.is_none_or(|lowest| price <= lowest) {
+                self.lowest_listing_price = Some(price);
+            } else if old_price == self.lowest_listing_price {
+                self.refresh_lowest_listing_price();
+            }
+
+            Ok(())
+        }
+
+        /// stop purchases of token published for sale, keeping its listing and price
+        #[ink(message)]
+        pub fn hold_listing(&mut self, id: TokenId) -> Result<(), Error> {
+            self.ensure_can_manage_listing(id)?;
+            self.held_listings.insert(id, &());
+            Ok(())
+        }
+
+        /// allow purchases of token held from sale again
+        #[ink(message)]
+        pub fn release_listing(&mut self, id: TokenId) -> Result<(), Error> {
+            self.ensure_can_manage_listing(id)?;
+            self.held_listings.remove(id);
+            Ok(())
+        }
+
+        /// get minimal price of listing
+        #[ink(message)]
+        pub fn floor_price(&self) -> Option<Balance> {
+            self.floor_price
+        }
+
+        /// set minimal price of listing, `None` disables the check. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_floor_price(&mut self, floor_price: Option<Balance>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            self.floor_price = floor_price;
+            Ok(())
+        }
+
+        /// get all tokens which published for sale
+        #[ink(message)]
+        pub fn get_tokens_for_sale(&self) -> Vec<ForSale> {
+            self.public_listings()
+                .filter_map(|id| self.prices.get(id).map(|price| ForSale { id: *id, price, on_hold: self.held_listings.contains(id) }))
+                .collect()
+        }
+
+        /// get page of tokens which published for sale, at most `MAX_PAGE_SIZE` tokens
+        #[ink(message)]
+        pub fn get_listings_paged(&self, offset: u32, limit: u32) -> Vec<ForSale> {
+            self.public_listings()
+                .skip(offset as usize)
+                .take(limit.min(MAX_PAGE_SIZE) as usize)
+                .filter_map(|id| self.prices.get(id).map(|price| ForSale { id: *id, price, on_hold: self.held_listings.contains(id) }))
+                .collect()
+        }
+
+        /// get number of tokens which published for sale, private listings are not counted
+        /// the same as they are not returned by `get_listings_paged`
+        #[ink(message)]
+        pub fn listings_count(&self) -> u32 {
+            (self.tokens_for_sale.len() as u32).saturating_sub(self.private_listings_count)
+        }
+
+        /// get number of tokens which published for sale, private listings included
+        #[ink(message)]
+        pub fn count_for_sale(&self) -> u32 {
+            self.tokens_for_sale.len() as u32
+        }
+
+        /// get number of tokens which published for sale by `owner`
+        #[ink(message)]
+        pub fn count_for_sale_by_owner(&self, owner: AccountId) -> u32 {
+            self.listings_count_by_seller.get(owner).unwrap_or(0)
+        }
+
+        /// get sum of prices of tokens published for sale by `owner`, `Balance::MAX` if it overflows
+        #[ink(message)]
+        pub fn listed_value_of(&self, owner: AccountId) -> Balance {
+            self.tokens_for_sale
+                .iter()
+                .filter(|id| self.sellers.get(*id) == Some(owner))
+                .filter_map(|id| self.prices.get(id))
+                .try_fold(0, |sum: Balance, price| sum.checked_add(price))
+                .unwrap_or(Balance::MAX)
+        }
+
+        /// get page of public listings with seller, metadata, royalty and marketplace fee,
+        /// at most `MAX_BATCH_SIZE` of them. Listings which sellers no longer own the token are skipped.
+        #[ink(message)]
+        pub fn detailed_listings(&self, offset: u32, limit: u32) -> Vec<DetailedListing> {
+            self.public_listings()
+                .filter_map(|id| {
+                    let seller = self.sellers.get(id)?;
+                    if !self.custodial_listings.contains(id) && self.owner_of(*id) != Some(seller) {
+                        return None;
+                    }
+                    Some(DetailedListing {
+                        id: *id,
+                        seller,
+                        price: self.prices.get(id)?,
+                        metadata: self.metadata_of(*id)?,
+                        // capped royalty sums to at most `max_royalty_bps`
+                        royalty_bps: self.capped_royalty(*id).iter().map(|(_, bps)| *bps).sum(),
+                        fee_bps: self.fee_bps,
+                    })
+                })
+                .skip(offset as usize)
+                .take((limit as usize).min(MAX_BATCH_SIZE))
+                .collect()
+        }
+
+        /// remove tokens from saling
+        #[ink(message)]
+        pub fn remove_from_sale(&mut self, id: TokenId) -> Result<(), Error>{
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            if !self.is_seller_of(&caller, id) {
+                return Err(Error::NotApproved);
+            };
+            if !self.prices.contains(id) {
+                return Err(Error::NotForSale);
+            }
+            self.unlist(&caller, id)?;
+            
+            Ok(())
+        }
+
+        /// return token stuck in custody to its recorded seller and clear what is left of its listing,
+        /// only the owner can call it
+        #[ink(message)]
+        pub fn recover_listing(&mut self, id: TokenId) -> Result<(), Error> {
+            self.ensure_owner()?;
+            if !self.is_in_custody(id) || self.auctions.contains(id) {
+                return Err(Error::NotAllowed);
+            }
+            let seller = self.sellers.get(id).ok_or(Error::NotForSale)?;
+
+            let contract = self.env().account_id();
+            self.transfer_token_from(&contract, &seller, id)?;
+            if self.sale_index.contains(id) {
+                self.remove_listing(id)?;
+            } else {
+                self.sellers.remove(id);
+                self.custodial_listings.remove(id);
+                self.private_buyers.remove(id);
+                self.held_listings.remove(id);
+            }
+            Ok(())
+        }
+
+        /// remove all tokens of caller from saling, returns number of removed tokens
+        #[ink(message)]
+        pub fn cancel_all_listings(&mut self) -> Result<u32, Error> {
+            let caller = self.env().caller();
+            let ids: Vec<TokenId> = self
+                .tokens_for_sale
+                .iter()
+                .copied()
+                .filter(|id| self.is_seller_of(&caller, *id))
+                .collect();
+            for id in ids.iter() {
+                self.unlist(&caller, *id)?;
+            }
+            Ok(ids.len() as u32)
+        }
+
+        /// remove listings of `ids` which sellers no longer own the token, anyone can call it,
+        /// returns number of removed listings
+        #[ink(message)]
+        pub fn sweep_stale_listings(&mut self, ids: Vec<TokenId>) -> Result<u32, Error> {
+            if ids.len() > MAX_BATCH_SIZE {
+                return Err(Error::NotAllowed);
+            }
+            let mut removed = 0;
+            for id in ids {
+                if !self.sale_index.contains(id) || self.custodial_listings.contains(id) {
+                    continue;
+                }
+                let seller = match self.sellers.get(id) {
+                    Some(seller) if self.owner_of(id) != Some(seller) => seller,
+                    _ => continue,
+                };
+                self.unlist(&seller, id)?;
+                removed += 1;
+            }
+            Ok(removed)
+        }
+
+        /// buy token for sale, overpayment is returned, returns the charged price
+        #[ink(message, payable)]
+        pub fn buy_nft(&mut self, id: TokenId) -> Result<Balance, Error>{
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            let token_price = self.prices.get(id).ok_or(Error::NotForSale)?;
+            let seller = self.sellers.get(id).ok_or(Error::NotForSale)?;
+            let token_owner = self.owner_of(id).ok_or(Error::TokenNotFound)?;
+            // the recorded seller can differ from the current owner: custodial listings are owned
+            // by the contract and a stale listing may point to a token already moved to the buyer,
+            // so neither the seller nor the owner may buy the token
+            if seller == caller || token_owner == caller { // не продаем нфт ее же владельцу
+                return Err(Error::NotApproved);
+            };
+            if self.private_buyers.get(id).is_some_and(|buyer| buyer != caller) {
+                return Err(Error::NotWhitelisted);
+            }
+            if self.held_listings.contains(id) {
+                return Err(Error::ListingOnHold);
+            }
+            let transfered_price = self.env().transferred_value();
+            match self.payment_token {
+                // PSP22 sale must not lock native balance in the contract
+                Some(_) if transfered_price > 0 => return Err(Error::NotAllowed),
+                None if token_price > transfered_price => return Err(Error::NotEnoughSent),
+                _ => {}
+            }
+            let total_volume = checked_add(self.total_volume, token_price)?;
+
+            // the listing is stale once the seller lost the token or the contract lost its approval
+            let contract = self.env().account_id();
+            if !self.custodial_listings.contains(id)
+                && (token_owner != seller || !self.approved_or_owner(Some(contract), id))
+            {
+                return Err(Error::NotForSale);
+            }
+            self.ensure_not_blacklisted(&seller)?;
+            self.ensure_not_blacklisted(&caller)?;
+            if let Some(payment_token) = self.payment_token {
+                self.psp22_transfer_from(payment_token, caller, contract, token_price)?;
+            }
+            self.pay_sale(id, &seller, token_price, self.payment_token)?;
+            if self.payment_token.is_none() && transfered_price > token_price {
+                self.pay(caller, transfered_price - token_price)?;
+            }
+            
+            self.transfer_token_from(&token_owner, &caller, id)?;
+            self.remove_listing(id)?;
+
+            self.total_volume = total_volume;
+            if self.highest_sale.is_none_or(|(_, price)| token_price > price) {
+                self.highest_sale = Some((id, token_price));
+            }
+            self.record_sale(id, seller, caller, token_price);
+            self.env().emit_event(Sold {
+                seller,
+                buyer: caller,
+                id,
+                price: token_price,
+                block: self.env().block_number(),
+                timestamp: self.env().block_timestamp(),
+            });
+            
+            Ok(token_price)
+        }
+
+        /// get split of payment for token published for sale if bought now
+        #[ink(message)]
+        pub fn preview_buy(&self, id: TokenId) -> Result<BuyBreakdown, Error> {
+            let price = self.prices.get(id).ok_or(Error::NotForSale)?;
+            self.sale_breakdown(id, price)
+        }
+
+        /// get last sales of token as (seller, buyer, price, block) from oldest to newest
+        #[ink(message)]
+        pub fn sale_history_of(&self, id: TokenId) -> Vec<(AccountId, AccountId, Balance, BlockNumber)> {
+            self.sale_history.get(id).unwrap_or_default()
+        }
+
+        /// get sales of all tokens as (token, seller, buyer, price, block) recorded at or after `block`,
+        /// oldest first, at most `limit` and `MAX_BATCH_SIZE` of them. Only the latest
+        /// `MAX_RECENT_SALES` sales are kept.
+        #[ink(message)]
+        pub fn sales_since(&self, block: BlockNumber, limit: u32) -> Vec<(TokenId, AccountId, AccountId, Balance, BlockNumber)> {
+            let sale = |number: u32| self.recent_sales.get(number % MAX_RECENT_SALES);
+            // sales are recorded in block order, so search for the first one at `block`
+            let (mut low, mut high) = (self.sales_count.saturating_sub(MAX_RECENT_SALES), self.sales_count);
+            while low < high {
+                let middle = low + (high - low) / 2;
+                match sale(middle) {
+                    Some((.., sold_at)) if sold_at < block => low = middle + 1,
+                    _ => high = middle,
+                }
+            }
+            (low..self.sales_count)
+                .take((limit as usize).min(MAX_BATCH_SIZE))
+                .filter_map(sale)
+                .collect()
+        }
+
+        /// get sum of prices of all tokens sold via `buy_nft`
+        #[ink(message)]
+        pub fn total_volume(&self) -> Balance {
+            self.total_volume
+        }
+
+        /// get token sold via `buy_nft` for the highest price and the price
+        #[ink(message)]
+        pub fn highest_sale(&self) -> Option<(TokenId, Balance)> {
+            self.highest_sale
+        }
+
+        /// start sale of token with price declining from `start_price` to `end_price` during `duration` blocks
+        #[ink(message)]
+        pub fn start_dutch_auction(
+            &mut self,
+            id: TokenId,
+            start_price: Balance,
+            end_price: Balance,
+            duration: BlockNumber,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            if !self.is_owner_of(Some(caller), id) {
+                return Err(Error::NotApproved);
+            };
+            if self.prices.contains(id) || self.in_dutch_auction(id) || self.auctions.contains(id) {
+                return Err(Error::AlreadyForSale);
+            }
+            if duration == 0 || start_price < end_price {
+                return Err(Error::NotAllowed);
+            }
+            self.ensure_valid_price(end_price)?;
+
+            self.dutch_auctions.insert(id, &DutchAuction {
+                seller: caller,
+                start_price,
+                end_price,
+                start_block: self.env().block_number(),
+                duration,
+            });
+
+            Ok(())
+        }
+
+        /// cancel sale of token with declining price, only the seller can call it
+        #[ink(message)]
+        pub fn cancel_dutch_auction(&mut self, id: TokenId) -> Result<(), Error> {
+            let auction = self.dutch_auctions.get(id).ok_or(Error::NotForSale)?;
+            if auction.seller != self.env().caller() {
+                return Err(Error::NotApproved);
+            }
+            self.dutch_auctions.remove(id);
+            Ok(())
+        }
+
+        /// get current price of token sold with declining price
+        #[ink(message)]
+        pub fn dutch_price(&self, id: TokenId) -> Result<Balance, Error> {
+            let auction = self.dutch_auctions.get(id).ok_or(Error::NotForSale)?;
+            Ok(auction.price_at(self.env().block_number()))
+        }
+
+        /// buy token sold with declining price, overpayment is refunded
+        #[ink(message, payable)]
+        pub fn buy_dutch(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let auction = self.dutch_auctions.get(id).ok_or(Error::NotForSale)?;
+            if auction.seller == caller {
+                return Err(Error::NotApproved);
+            }
+            if !self.is_owner_of(Some(auction.seller), id) {
+                return Err(Error::NotForSale);
+            }
+            let transfered_price = self.env().transferred_value();
+            let price = auction.price_at(self.env().block_number());
+            if price > transfered_price {
+                return Err(Error::NotEnoughSent);
+            }
+
+            self.pay_sale(id, &auction.seller, price, None)?;
+            if transfered_price > price {
+                self.pay(caller, transfered_price - price)?;
+            }
+
+            self.transfer_token_from(&auction.seller, &caller, id)?;
+            self.dutch_auctions.remove(id);
+
+            Ok(())
+        }
+
+        /// start English auction of token lasting `duration` blocks, the token is held by the contract
+        /// and sold only if the highest bid meets `reserve`. Each bid must beat the previous one
+        /// by at least `min_increment`. If `buy_now_price` is set, a buyer can end the auction early
+        /// paying that price.
+        #[ink(message)]
+        pub fn create_auction(
+            &mut self,
+            id: TokenId,
+            reserve: Balance,
+            min_increment: Balance,
+            buy_now_price: Option<Balance>,
+            duration: BlockNumber,
+        ) -> Result<(), Error> {
+            let caller = self.env().caller();
+            self.ensure_can_list(&caller, id, reserve)?;
+            if duration == 0 || buy_now_price.is_some_and(|price| price < reserve) {
+                return Err(Error::NotAllowed);
+            }
+
+            let contract = self.env().account_id();
+            self.transfer_token_from(&caller, &contract, id)?;
+            self.auctions.insert(id, &Auction {
+                seller: caller,
+                reserve,
+                min_increment,
+                buy_now_price,
+                end_block: self.env().block_number().saturating_add(duration),
+                highest_bid: None,
+            });
+            Ok(())
+        }
+
+        /// get running auction of token
+        #[ink(message)]
+        pub fn get_auction(&self, id: TokenId) -> Option<Auction> {
+            self.auctions.get(id)
+        }
+
+        /// bid the transferred value in auction, the previous highest bid is refunded
+        #[ink(message, payable)]
+        pub fn bid(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let amount = self.env().transferred_value();
+            let mut auction = self.auctions.get(id).ok_or(Error::NotForSale)?;
+            if self.env().block_number() >= auction.end_block {
+                return Err(Error::AuctionEnded);
+            }
+            if auction.seller == caller {
+                return Err(Error::NotApproved);
+            }
+            self.ensure_not_blacklisted(&caller)?;
+            let min_bid = match auction.highest_bid {
+                Some((_, highest)) => highest.saturating_add(auction.min_increment.max(1)),
+                None => auction.reserve.max(1),
+            };
+            if amount < min_bid {
+                return Err(Error::BidTooLow);
+            }
+
+            if let Some((bidder, highest)) = auction.highest_bid {
+                self.pay(bidder, highest)?;
+            }
+            auction.highest_bid = Some((caller, amount));
+            self.auctions.insert(id, &auction);
+            Ok(())
+        }
+
+        /// end running auction paying its buy now price, the highest bid is refunded
+        /// and overpayment is returned
+        #[ink(message, payable)]
+        pub fn buy_now(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let transfered_price = self.env().transferred_value();
+            let auction = self.auctions.get(id).ok_or(Error::NotForSale)?;
+            let price = auction.buy_now_price.ok_or(Error::NotForSale)?;
+            if self.env().block_number() >= auction.end_block {
+                return Err(Error::AuctionEnded);
+            }
+            if auction.seller == caller {
+                return Err(Error::NotApproved);
+            }
+            self.ensure_not_blacklisted(&caller)?;
+            if price > transfered_price {
+                return Err(Error::NotEnoughSent);
+            }
+
+            let contract = self.env().account_id();
+            self.auctions.remove(id);
+            if let Some((bidder, highest)) = auction.highest_bid {
+                self.pay(bidder, highest)?;
+            }
+            self.pay_sale(id, &auction.seller, price, None)?;
+            if transfered_price > price {
+                self.pay(caller, transfered_price - price)?;
+            }
+            self.transfer_token_from(&contract, &caller, id)?;
+            Ok(())
+        }
+
+        /// finish ended auction: sell token to the highest bidder if the bid meets the reserve,
+        /// otherwise return token to the seller and refund the bidder, anyone can call it
+        #[ink(message)]
+        pub fn settle_auction(&mut self, id: TokenId) -> Result<(), Error> {
+            let auction = self.auctions.get(id).ok_or(Error::NotForSale)?;
+            if self.env().block_number() < auction.end_block {
+                return Err(Error::AuctionNotEnded);
+            }
+
+            let contract = self.env().account_id();
+            self.auctions.remove(id);
+            match auction.highest_bid {
+                Some((bidder, amount)) if amount >= auction.reserve => {
+                    self.pay_sale(id, &auction.seller, amount, None)?;
+                    self.transfer_token_from(&contract, &bidder, id)?;
+                }
+                _ => {
+                    if let Some((bidder, amount)) = auction.highest_bid {
+                        self.pay(bidder, amount)?;
+                    }
+                    self.transfer_token_from(&contract, &auction.seller, id)?;
+                    self.env().emit_event(AuctionFailed {
+                        id,
+                        highest_bid: auction.highest_bid,
+                        block: self.env().block_number(),
+                        timestamp: self.env().block_timestamp(),
+                    });
+                }
+            }
+            Ok(())
+        }
+
+        /// cancel auction without bids and return token to the seller, only the seller can call it
+        #[ink(message)]
+        pub fn cancel_auction(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let auction = self.auctions.get(id).ok_or(Error::NotForSale)?;
+            if auction.seller != caller {
+                return Err(Error::NotApproved);
+            }
+            if auction.highest_bid.is_some() {
+                return Err(Error::NotAllowed);
+            }
+
+            let contract = self.env().account_id();
+            self.auctions.remove(id);
+            self.transfer_token_from(&contract, &auction.seller, id)?;
+            Ok(())
+        }
+
+        /// extend running auction by `extra_blocks`, only the seller or the collection owner can call it
+        #[ink(message)]
+        pub fn extend_auction(&mut self, id: TokenId, extra_blocks: BlockNumber) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let mut auction = self.auctions.get(id).ok_or(Error::NotForSale)?;
+            if auction.seller != caller && self.owner != caller {
+                return Err(Error::NotApproved);
+            }
+            if self.env().block_number() >= auction.end_block {
+                return Err(Error::AuctionEnded);
+            }
+            if extra_blocks == 0 {
+                return Err(Error::NotAllowed);
+            }
+
+            auction.end_block = auction.end_block.saturating_add(extra_blocks);
+            self.auctions.insert(id, &auction);
+            Ok(())
+        }
+
+        /// make offer for token escrowing the transferred value until it is accepted, cancelled
+        /// or reclaimed after block `expires_at`
+        #[ink(message, payable)]
+        pub fn make_offer(&mut self, id: TokenId, expires_at: BlockNumber) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let amount = self.env().transferred_value();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            }
+            if self.is_owner_of(Some(caller), id) || amount == 0 || expires_at < self.env().block_number() {
+                return Err(Error::NotAllowed);
+            }
+            if self.offers.contains((id, caller)) {
+                return Err(Error::NotAllowed);
+            }
+            let mut offerors = self.offerors.get(id).unwrap_or_default();
+            if offerors.len() >= MAX_OFFERS_PER_TOKEN {
+                return Err(Error::TooManyOffers);
+            }
+
+            offerors.push(caller);
+            self.offerors.insert(id, &offerors);
+            self.offers.insert((id, caller), &(amount, expires_at));
+            if self.best_offers.get(id).is_none_or(|(_, best)| amount > best) {
+                self.best_offers.insert(id, &(caller, amount));
+            }
+            Ok(())
+        }
+
+        /// cancel offer of caller for token and refund the escrow
+        #[ink(message)]
+        pub fn cancel_offer(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let (amount, _) = self.offers.get((id, caller)).ok_or(Error::OfferNotFound)?;
+            self.remove_offer(id, &caller);
+            self.pay(caller, amount)?;
+            Ok(())
+        }
+
+        /// refund escrow of expired offer to its maker, anyone can call it
+        #[ink(message)]
+        pub fn reclaim_expired_offer(&mut self, id: TokenId, offeror: AccountId) -> Result<(), Error> {
+            let (amount, expires_at) = self.offers.get((id, offeror)).ok_or(Error::OfferNotFound)?;
+            if self.env().block_number() <= expires_at {
+                return Err(Error::NotAllowed);
+            }
+            self.remove_offer(id, &offeror);
+            self.pay(offeror, amount)?;
+            Ok(())
+        }
+
+        /// sell token to `offeror` for its escrowed offer
+        #[ink(message)]
+        pub fn accept_offer(&mut self, id: TokenId, offeror: AccountId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            }
+            if !self.is_owner_of(Some(caller), id) {
+                return Err(Error::NotApproved);
+            }
+            let (amount, expires_at) = self.offers.get((id, offeror)).ok_or(Error::OfferNotFound)?;
+            if self.env().block_number() > expires_at {
+                return Err(Error::ListingExpired);
+            }
+            self.ensure_not_blacklisted(&offeror)?;
+
+            self.remove_offer(id, &offeror);
+            if self.prices.contains(id) {
+                self.remove_listing(id)?;
+            }
+            self.pay_sale(id, &caller, amount, None)?;
+            self.transfer_token_from(&caller, &offeror, id)?;
+            Ok(())
+        }
+
+        /// get offer of `offeror` for token and the last block it can be accepted
+        #[ink(message)]
+        pub fn offer_of(&self, id: TokenId, offeror: AccountId) -> Option<(Balance, BlockNumber)> {
+            self.offers.get((id, offeror))
+        }
+
+        /// get highest standing offer for token
+        #[ink(message)]
+        pub fn best_offer(&self, id: TokenId) -> Option<(AccountId, Balance)> {
+            self.best_offers.get(id)
+        }
+
+        /// Returns the account which minted the token.
+        #[ink(message)]
+        pub fn creator_of(&self, id: TokenId) -> Option<AccountId> {
+            self.creators.get(id)
+        }
+
+        /// Set royalty recipients of token with their basis points. Only the creator of token can call it.
+        ///
+        /// Basis points of all recipients must sum to at most `MAX_BPS`.
+        #[ink(message)]
+        pub fn set_royalty(&mut self, id: TokenId, recipients: Vec<(AccountId, u16)>) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            }
+            if self.creators.get(id) != Some(caller) {
+                return Err(Error::NotAllowed);
+            }
+            ensure_valid_royalty(&recipients)?;
+            self.ensure_royalty_within_cap(&recipients)?;
+            self.royalties.insert(id, &recipients);
+            Ok(())
+        }
+
+        /// Returns royalty recipients of token with their basis points,
+        /// the default royalty if token has no own royalty.
+        #[ink(message)]
+        pub fn royalty_recipients(&self, id: TokenId) -> Vec<(AccountId, u16)> {
+            self.royalties
+                .get(id)
+                .unwrap_or_else(|| self.default_royalty.into_iter().collect())
+        }
+
+        /// Returns max sum of royalty basis points of token.
+        #[ink(message)]
+        pub fn max_royalty_bps(&self) -> u16 {
+            self.max_royalty_bps
+        }
+
+        /// Set max sum of royalty basis points of token, royalties set above it are scaled down on sale.
+        /// Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_max_royalty_bps(&mut self, max_royalty_bps: u16) -> Result<(), Error> {
+            self.ensure_owner()?;
+            if max_royalty_bps > MAX_BPS {
+                return Err(Error::InvalidRoyalty);
+            }
+            self.max_royalty_bps = max_royalty_bps;
+            Ok(())
+        }
+
+        /// Returns royalty of tokens without own royalty.
+        #[ink(message)]
+        pub fn default_royalty(&self) -> Option<(AccountId, u16)> {
+            self.default_royalty
+        }
+
+        /// Set royalty of tokens without own royalty, `None` disables it. Only the collection owner can call it.
+        #[ink(message)]
+        pub fn set_default_royalty(&mut self, royalty: Option<(AccountId, u16)>) -> Result<(), Error> {
+            self.ensure_owner()?;
+            if let Some(royalty) = royalty {
+                ensure_valid_royalty(&[royalty])?;
+                self.ensure_royalty_within_cap(&[royalty])?;
+            }
+            self.default_royalty = royalty;
+            Ok(())
+        }
+
+        /// Returns total royalty paid to `who` from sales of tokens.
+        #[ink(message)]
+        pub fn royalties_earned_of(&self, who: AccountId) -> Balance {
+            self.royalties_earned.get(who).unwrap_or(0)
+        }
+
+        /// Returns total royalty paid to all recipients when token is sold for `sale_price`.
+        ///
+        /// Royalty is resolved as: own royalty of token, then the collection default royalty,
+        /// then none, which returns 0. Recipients are listed by `royalty_recipients`.
+        #[ink(message)]
+        pub fn royalty_info(&self, id: TokenId, sale_price: Balance) -> Balance {
+            // recipients sum to at most `MAX_BPS`, so royalty never exceeds `sale_price`
+            self.royalty_of(id, sale_price).unwrap_or(sale_price)
+        }
+
+        /// publish set of at most `MAX_BATCH_SIZE` tokens for sale as one lot, every token is checked
+        /// the same way as a single listing
+        #[ink(message)]
+        pub fn publish_bundle_for_sale(&mut self, ids: Vec<TokenId>, price: Balance) -> Result<u32, Error> {
+            let caller = self.env().caller();
+            if ids.is_empty() || ids.len() > MAX_BATCH_SIZE {
+                return Err(Error::NotAllowed);
+            }
+            for (index, id) in ids.iter().enumerate() {
+                if ids[..index].contains(id) {
+                    return Err(Error::NotAllowed);
+                }
+                self.ensure_can_list(&caller, *id, price)?;
+                if !self.approved_or_owner(Some(self.env().account_id()), *id) {
+                    return Err(Error::MarketplaceNotApproved);
+                }
+            }
+
+            let bundle_id = self.next_bundle_id;
+            self.next_bundle_id = bundle_id.checked_add(1).ok_or(Error::CannotInsert)?;
+            self.bundles.insert(bundle_id, &ids);
+            self.bundle_prices.insert(bundle_id, &price);
+            self.bundle_sellers.insert(bundle_id, &caller);
+
+            Ok(bundle_id)
+        }
+
+        /// get tokens of bundle
+        #[ink(message)]
+        pub fn get_bundle(&self, bundle_id: u32) -> Option<(Vec<TokenId>, Balance)> {
+            let ids = self.bundles.get(bundle_id)?;
+            let price = self.bundle_prices.get(bundle_id)?;
+            Some((ids, price))
+        }
+
+        /// buy all tokens of bundle, overpayment is returned
+        #[ink(message, payable)]
+        pub fn buy_bundle(&mut self, bundle_id: u32) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let ids = self.bundles.get(bundle_id).ok_or(Error::NotForSale)?;
+            let price = self.bundle_prices.get(bundle_id).ok_or(Error::NotForSale)?;
+            let seller = self.bundle_sellers.get(bundle_id).ok_or(Error::NotForSale)?;
+            if seller == caller {
+                return Err(Error::NotApproved);
+            }
+            let transfered_price = self.env().transferred_value();
+            if price > transfered_price {
+                return Err(Error::NotEnoughSent);
+            }
+            // check every token before moving anything so the bundle is sold entirely or not at all
+            if ids.iter().any(|id| !self.is_owner_of(Some(seller), *id)) {
+                return Err(Error::NotApproved);
+            }
+
+            // every token carries an equal share of the price for its royalty and the marketplace fee,
+            // the first one also carries the remainder of the division
+            let count = Balance::try_from(ids.len()).map_err(|_| Error::Overflow)?;
+            let share = price / count;
+            for (index, id) in ids.iter().enumerate() {
+                let token_price = if index == 0 { share + price % count } else { share };
+                self.pay_sale(*id, &seller, token_price, None)?;
+            }
+            if transfered_price > price {
+                self.pay(caller, transfered_price - price)?;
+            }
+
+            for id in ids.iter() {
+                self.transfer_token_from(&seller, &caller, *id)?;
+                if self.prices.contains(id) {
+                    self.remove_listing(*id)?;
+                }
+            }
+
+            self.bundles.remove(bundle_id);
+            self.bundle_prices.remove(bundle_id);
+            self.bundle_sellers.remove(bundle_id);
+
+            Ok(())
+        }
+
+        /// Transfer owned token.
+        #[ink(message)]
+        pub fn get_nft_info(&self, id: TokenId) -> Result<NftData, Error> {
+            self.metadata_of(id).ok_or(Error::TokenNotFound)
+        }
+
+        /// Returns owner, metadata, approval, listing and creator of token in one call.
+        #[ink(message)]
+        pub fn token_info(&self, id: TokenId) -> Result<TokenInfo, Error> {
+            let owner = self.owner_of(id).ok_or(Error::TokenNotFound)?;
+            let price = self.prices.get(id);
+            Ok(TokenInfo {
+                owner,
+                metadata: self.metadata_of(id).ok_or(Error::TokenNotFound)?,
+                approved: self.get_approved(id),
+                for_sale: price.is_some(),
+                price,
+                creator: self.creators.get(id),
+            })
+        }
+
+        /// Return metadata of many tokens, `None` for not existing tokens.
+        /// Only first `MAX_BATCH_SIZE` ids are processed.
+        #[ink(message)]
+        pub fn get_nft_info_batch(&self, ids: Vec<TokenId>) -> Vec<(TokenId, Option<NftData>)> {
+            ids.into_iter()
+                .take(MAX_BATCH_SIZE)
+                .map(|id| (id, self.metadata_of(id)))
+                .collect()
+        }
+
+        /// Returns whether token `id` exists together with its owner.
+        #[ink(message)]
+        pub fn lookup(&self, id: TokenId) -> (bool, Option<AccountId>) {
+            (self.exists(id), self.owner_of(id))
+        }
+
+        /// Returns owners of many tokens, `None` for nonexistent ones.
+        #[ink(message)]
+        pub fn owners_of_batch(&self, ids: Vec<TokenId>) -> Vec<(TokenId, Option<AccountId>)> {
+            ids.into_iter()
+                .take(MAX_BATCH_SIZE)
+                .map(|id| (id, self.owner_of(id)))
+                .collect()
+        }
+
+        /// Burns tokens `burn_ids` of the caller and mints one new token with `data` to the caller.
+        ///
+        /// Every token is checked before burning any of them, so nothing changes if one can not be burned.
+        #[ink(message)]
+        pub fn burn_and_mint(&mut self, burn_ids: Vec<TokenId>, new_data: NftData) -> Result<TokenId, Error> {
+            let caller = self.env().caller();
+            if burn_ids.is_empty() || burn_ids.len() > MAX_BATCH_SIZE {
+                return Err(Error::NotAllowed);
+            }
+            self.ensure_supply_unlocked()?;
+            for (i, id) in burn_ids.iter().enumerate() {
+                if burn_ids[..i].contains(id) {
+                    return Err(Error::NotAllowed);
+                }
+                if !self.exists(*id) {
+                    return Err(Error::TokenNotFound);
+                }
+                if !self.is_owner_of(Some(caller), *id) {
+                    return Err(Error::NotOwner);
+                }
+                if self.prices.contains(id) || self.in_dutch_auction(*id) || self.auctions.contains(id) {
+                    return Err(Error::AlreadyForSale);
+                }
+            }
+
+            for id in burn_ids {
+                self.burn_token(&caller, id)?;
+            }
+            let id = self.take_next_token_id()?;
+            self.mint_token_to(&caller, id, &new_data)?;
+            Ok(id)
+        }
+
+        /// Takes token `external_id` of ERC-721 `external_contract` into custody of this contract
+        /// and mints a wrapped token for it to the caller. The caller has to approve this contract
+        /// in the external collection first.
+        #[ink(message)]
+        pub fn wrap(&mut self, external_contract: AccountId, external_id: u32) -> Result<TokenId, Error> {
+            let caller = self.env().caller();
+            self.ensure_can_mint(1)?;
+            let contract = self.env().account_id();
+            self.external_transfer_from(external_contract, caller, contract, external_id)?;
+
+            let id = self.take_next_token_id()?;
+            self.mint_token_to(&caller, id, &NftData { poebat: None })?;
+            self.wrapped.insert(id, &(external_contract, external_id));
+            Ok(id)
+        }
+
+        /// Burns wrapped token `id` and returns the underlying external token to the caller.
+        #[ink(message)]
+        pub fn unwrap(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let (external_contract, external_id) = self.wrapped.get(id).ok_or(Error::TokenNotFound)?;
+            if !self.is_owner_of(Some(caller), id) {
+                return Err(Error::NotOwner);
+            }
+            let contract = self.env().account_id();
+            self.external_transfer_from(external_contract, contract, caller, external_id)?;
+
+            self.wrapped.remove(id);
+            self.burn_token(&caller, id)?;
+            Ok(())
+        }
+
+        /// Returns external contract and token id wrapped by token `id`.
+        #[ink(message)]
+        pub fn wrapped_of(&self, id: TokenId) -> Option<(AccountId, u32)> {
+            self.wrapped.get(id)
+        }
+
+        /// Deletes an existing token. Only the owner can burn the token.
+        /// The caller must send at least the burn fee, overpayment is refunded.
+        #[ink(message, payable)]
+        pub fn burn(&mut self, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            let excess = self.ensure_fee_paid(self.burn_fee)?;
+            self.burn_token(&caller, id)?;
+            self.pay_fee(&caller, self.burn_fee, excess)
+        }
+
+        /// Deletes tokens of the caller among `count` consecutive ids starting at `from_id`, skipping
+        /// ids the caller does not own, has staked or wrapped. The burn fee is paid for every burned token.
+        /// Returns number of burned tokens.
+        #[ink(message, payable)]
+        pub fn burn_range(&mut self, from_id: TokenId, count: u32) -> Result<u32, Error> {
+            let caller = self.env().caller();
+            if count as usize > MAX_BATCH_SIZE {
+                return Err(Error::NotAllowed);
+            }
+            let ids: Vec<TokenId> = (0..count)
+                .map_while(|offset| from_id.checked_add(TokenId::from(offset)))
+                .filter(|id| self.is_owner_of(Some(caller), *id) && !self.staked_by.contains(id) && !self.wrapped.contains(id))
+                .collect();
+            let fee = self.burn_fee.checked_mul(ids.len() as Balance).ok_or(Error::Overflow)?;
+            let excess = self.ensure_fee_paid(fee)?;
+
+            for id in ids.iter() {
+                self.burn_token(&caller, *id)?;
+            }
+            self.pay_fee(&caller, fee, excess)?;
+            Ok(ids.len() as u32)
+        }
+
+        /// Deletes token `id` owned by `from`. Only an approved account or operator can call it.
+        #[ink(message)]
+        pub fn burn_from(&mut self, from: AccountId, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            }
+            if !self.approved_or_owner(Some(caller), id) {
+                return Err(Error::NotApproved);
+            }
+            self.burn_token(&from, id)
+        }
+
+        /// Deletes token `id` owned by `from` with its listings, approval, royalty, CID, metadata lock
+        /// and attributes.
+        fn burn_token(&mut self, from: &AccountId, id: TokenId) -> Result<(), Error> {
+            let owner = self.token_owner.get(id).ok_or(Error::TokenNotFound)?;
+            if owner != *from {
+                return Err(Error::NotOwner);
+            };
+            if self.staked_by.contains(id) {
+                return Err(Error::TokenStaked);
+            }
+            // burning would strand the external token, only `unwrap` returns it
+            if self.wrapped.contains(id) {
+                return Err(Error::NotAllowed);
+            }
+
+            let index = self.all_tokens.iter().position(|token| *token == id).ok_or(Error::CannotFetchValue)?;
+            self.remove_token_from(from, id)?;
+            self.all_tokens.remove(index);
+
+            self.clear_attributes(id);
+            self.clear_approval(id);
+            if self.prices.contains(id) {
+                self.remove_listing(id)?;
+            }
+            self.dutch_auctions.remove(id);
+            self.royalties.remove(id);
+            self.token_cids.remove(id);
+            self.frozen_metadata.remove(id);
+
+            self.env().emit_event(Transfer {
+                from: Some(*from),
+                to: Some(AccountId::from([0x0; 32])),
+                id,
+            });
+
+            Ok(())
+        }
+
+
+        /// Transfers token `id` `from` the sender to the `to` `AccountId`.
+        fn transfer_token_from(
+            &mut self,
+            from: &AccountId,
+            to: &AccountId,
+            id: TokenId,
+        ) -> Result<(), Error> {
+            self.move_token(from, to, id)?;
+            self.env().emit_event(Transfer {
+                from: Some(*from),
+                to: Some(*to),
+                id,
+            });
+            Ok(())
+        }
+
+        /// Moves token `id` `from` to `to` without emitting an event.
+        fn move_token(&mut self, from: &AccountId, to: &AccountId, id: TokenId) -> Result<(), Error> {
+            self.ensure_can_transfer(from, to, id)?;
+            self.clear_approval(id);
+            // a declining price sale of the previous owner must not revive if the token comes back
+            self.dutch_auctions.remove(id);
+            self.remove_token_from(from, id)?;
+            self.add_token_to(to, id)?;
+            if !self.is_custody_move(from, to) {
+                self.last_transfer.insert(id, &self.env().block_number());
+            }
+            if let Some(hook) = self.transfer_hook {
+                self.notify_transfer_hook(hook, *from, *to, id);
+            }
+            Ok(())
+        }
+
+        /// Returns error if `to` is the contract itself. Tokens sent there by users would be orphaned,
+        /// so only custody flows such as listings, auctions and wrapping move tokens to the contract.
+        fn ensure_not_contract(&self, to: &AccountId) -> Result<(), Error> {
+            if *to == self.env().account_id() {
+                return Err(Error::NotAllowed);
+            }
+            Ok(())
+        }
+
+        /// Returns error if token `id` can not be moved from `from` to `to`.
+        ///
+        /// Transfer of token to its owner is rejected with `SelfTransfer` instead of rewriting
+        /// storage and emitting a `Transfer` event which changes nothing.
+        fn ensure_can_transfer(&self, from: &AccountId, to: &AccountId, id: TokenId) -> Result<(), Error> {
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            if !self.is_owner_of(Some(*from), id) {
+                return Err(Error::NotApproved);
+            };
+            if from == to {
+                return Err(Error::SelfTransfer);
+            }
+            if *to == AccountId::from([0x0; 32]) {
+                return Err(Error::NotAllowed);
+            };
+            if self.staked_by.contains(id) {
+                return Err(Error::TokenStaked);
+            }
+            self.ensure_not_blacklisted(from)?;
+            self.ensure_not_blacklisted(to)?;
+            if self.is_custody_move(from, to) {
+                return Ok(());
+            }
+            self.ensure_cooldown_passed(id)
+        }
+
+        /// Returns true if the token moves into or out of custody of the contract. Such moves
+        /// neither wait for nor start the transfer cooldown, which applies between holders only.
+        fn is_custody_move(&self, from: &AccountId, to: &AccountId) -> bool {
+            let contract = self.env().account_id();
+            *from == contract || *to == contract
+        }
+
+        /// Transfers `amount` from the contract balance to `to`, emits `TransferFailed` on error.
+        fn pay(&mut self, to: AccountId, amount: Balance) -> Result<(), Error> {
+            let result = self.transfer_native(to, amount);
+            if result.is_err() {
+                self.env().emit_event(TransferFailed { to, amount });
+            }
+            result
+        }
+
+        /// Transfers `amount` from the contract balance to `to`. If `to` rejects the transfer,
+        /// the amount is credited to it instead, so a sale cannot be blocked by its recipients.
+        fn pay_or_credit(&mut self, to: AccountId, amount: Balance) -> Result<(), Error> {
+            match self.pay(to, amount) {
+                Err(Error::CannotMakeTransfer) => self.credit_failed_payment(to, amount),
+                result => result,
+            }
+        }
+
+        /// Transfers `amount` from the contract balance to `to`.
+        fn transfer_native(&self, to: AccountId, amount: Balance) -> Result<(), Error> {
+            if self.env().balance() < amount {
+                return Err(Error::InsufficientBalance);
+            }
+            self.env().transfer(to, amount).map_err(|_| Error::CannotMakeTransfer)
+        }
+
+        /// Credits `amount` which `to` rejected to its pending withdrawal.
+        fn credit_failed_payment(&mut self, to: AccountId, amount: Balance) -> Result<(), Error> {
+            let pending = checked_add(self.pending_withdrawal_of(to), amount)?;
+            self.pending_withdrawals.insert(to, &pending);
+            Ok(())
+        }
+
+        /// Transfers `amount` to `to` in PSP22 `token`, in native balance if none.
+        fn pay_in(&mut self, token: Option<AccountId>, to: AccountId, amount: Balance) -> Result<(), Error> {
+            match token {
+                Some(token) => self.psp22_transfer_from(token, self.env().account_id(), to, amount),
+                None => self.pay(to, amount),
+            }
+        }
+
+        /// Pays sale proceeds `amount` to `to` in PSP22 `token`, in native balance if none,
+        /// crediting them to `to` if it rejects a native transfer.
+        fn pay_proceeds_in(&mut self, token: Option<AccountId>, to: AccountId, amount: Balance) -> Result<(), Error> {
+            match token {
+                Some(token) => self.psp22_transfer_from(token, self.env().account_id(), to, amount),
+                None => self.pay_or_credit(to, amount),
+            }
+        }
+
+        /// Pays `price` of token `id` received in PSP22 `token`, or in native balance if none,
+        /// to its royalty recipients, keeps the marketplace fee and pays the rest to `seller`.
+        fn pay_sale(&mut self, id: TokenId, seller: &AccountId, price: Balance, token: Option<AccountId>) -> Result<(), Error> {
+            let breakdown = self.sale_breakdown(id, price)?;
+            let kept = if token.is_some() { self.accrued_token_fees } else { self.accrued_fees };
+            let kept = checked_add(kept, breakdown.marketplace_fee)?;
+
+            for (recipient, bps) in self.capped_royalty(id) {
+                let royalty = checked_mul_bps(price, bps)?;
+                self.pay_proceeds_in(token, recipient, royalty)?;
+                let earned = self.royalties_earned_of(recipient).saturating_add(royalty);
+                self.royalties_earned.insert(recipient, &earned);
+            }
+            self.pay_proceeds_in(token, *seller, breakdown.seller_proceeds)?;
+            if token.is_some() {
+                self.accrued_token_fees = kept;
+            } else {
+                self.accrued_fees = kept;
+            }
+            Ok(())
+        }
+
+        /// Returns split of `price` of token `id` between marketplace fee, royalty and seller.
+        fn sale_breakdown(&self, id: TokenId, price: Balance) -> Result<BuyBreakdown, Error> {
+            let marketplace_fee = checked_mul_bps(price, self.fee_bps)?;
+            let royalty = self.royalty_of(id, price)?;
+            let seller_proceeds = checked_sub(checked_sub(price, marketplace_fee)?, royalty)?;
+            Ok(BuyBreakdown {
+                price,
+                marketplace_fee,
+                royalty,
+                seller_proceeds,
+            })
+        }
+
+        /// Returns error if basis points of royalty recipients sum to more than the royalty cap.
+        fn ensure_royalty_within_cap(&self, recipients: &[(AccountId, u16)]) -> Result<(), Error> {
+            let total: u32 = recipients.iter().map(|(_, bps)| u32::from(*bps)).sum();
+            if total > u32::from(self.max_royalty_bps) {
+                return Err(Error::RoyaltyTooHigh);
+            }
+            Ok(())
+        }
+
+        /// Returns royalty recipients of token `id` with basis points scaled down
+        /// proportionally to fit the royalty cap.
+        fn capped_royalty(&self, id: TokenId) -> Vec<(AccountId, u16)> {
+            let recipients = self.royalty_recipients(id);
+            let total: u32 = recipients.iter().map(|(_, bps)| u32::from(*bps)).sum();
+            let cap = u32::from(self.max_royalty_bps);
+            if total <= cap {
+                return recipients;
+            }
+            recipients
+                .into_iter()
+                // `bps * cap / total` is below `bps`, so it fits `u16`
+                .map(|(recipient, bps)| (recipient, (u32::from(bps) * cap / total) as u16))
+                .collect()
+        }
+
+        /// Returns total royalty of token `id` sold for `price`, error if it overflows.
+        fn royalty_of(&self, id: TokenId, price: Balance) -> Result<Balance, Error> {
+            self.capped_royalty(id)
+                .into_iter()
+                .try_fold(0, |sum, (_, bps)| checked_add(sum, checked_mul_bps(price, bps)?))
+        }
+
+        /// Approves `to` for token `id` owned by `owner`.
+        fn approve_for(&mut self, owner: &AccountId, to: &AccountId, id: TokenId) -> Result<(), Error> {
+            self.ensure_can_approve(owner, to, id)?;
+            self.token_approvals.insert(id, to);
+            self.env().emit_event(Approval {
+                from: *owner,
+                to: *to,
+                id,
+            });
+            Ok(())
+        }
+
+        /// Returns error if `owner` cannot approve `to` for token `id`.
+        fn ensure_can_approve(&self, owner: &AccountId, to: &AccountId, id: TokenId) -> Result<(), Error> {
+            let token_owner = self.owner_of(id).ok_or(Error::TokenNotFound)?;
+            if !(token_owner == *owner || self.approved_for_all(token_owner, *owner)) {
+                return Err(Error::NotAllowed);
+            };
+            if *to == AccountId::from([0x0; 32]) {
+                return Err(Error::NotAllowed);
+            };
+            if self.token_approvals.contains(id) {
+                return Err(Error::CannotInsert);
+            }
+            Ok(())
+        }
+
+        /// Removes existing approval from token `id`.
+        fn clear_approval(&mut self, id: TokenId) {
+            self.token_approvals.remove(id);
+        }
+
+        /// Gets an operator on other Account's behalf.
+        fn approved_for_all(&self, owner: AccountId, operator: AccountId) -> bool {
+            self.operator_approvals.contains((&owner, &operator))
+        }
+
+        /// Returns true if the `AccountId` `from` is the owner of token `id`
+        /// or it has been approved on behalf of the token `id` owner.
+        fn approved_or_owner(&self, from: Option<AccountId>, id: TokenId) -> bool {
+            let owner = self.owner_of(id);
+            from != Some(AccountId::from([0x0; 32]))
+                && (from == owner
+                    || from == self.token_approvals.get(id)
+                    || self.approved_for_all(owner.unwrap_or_default(), from.unwrap_or_default()))
+        }
+
+        /// Returns error if `seller` cannot publish token `id` for sale at `price`.
+        fn ensure_can_list(&self, seller: &AccountId, id: TokenId, price: Balance) -> Result<(), Error> {
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            };
+            if !self.is_owner_of(Some(*seller), id) {
+                return Err(Error::NotApproved);
+            };
+            if self.sale_index.contains(id) || self.in_dutch_auction(id) || self.auctions.contains(id) {
+                return Err(Error::AlreadyForSale);
+            }
+            self.ensure_valid_price(price)
+        }
+
+        /// Returns tokens published for sale to anyone.
+        fn public_listings(&self) -> impl Iterator<Item = &TokenId> {
+            self.tokens_for_sale
+                .iter()
+                .filter(|id| !self.private_buyers.contains(*id))
+        }
+
+        /// Returns true if token `id` is sold with declining price by its current owner.
+        fn in_dutch_auction(&self, id: TokenId) -> bool {
+            self.dutch_auctions
+                .get(id)
+                .is_some_and(|auction| self.is_owner_of(Some(auction.seller), id))
+        }
+
+        /// Returns error if the caller can not manage listing of token `id` published for sale.
+        fn ensure_can_manage_listing(&self, id: TokenId) -> Result<(), Error> {
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            }
+            if !self.is_seller_of(&self.env().caller(), id) {
+                return Err(Error::NotApproved);
+            }
+            if !self.prices.contains(id) {
+                return Err(Error::NotForSale);
+            }
+            Ok(())
+        }
+
+        /// Returns true if `account` can manage listing of token `id`: it published the token
+        /// held by the contract or it owns the token.
+        fn is_seller_of(&self, account: &AccountId, id: TokenId) -> bool {
+            if self.custodial_listings.contains(id) {
+                self.sellers.get(id) == Some(*account)
+            } else {
+                self.is_owner_of(Some(*account), id)
+            }
+        }
+
+        /// Removes token `id` from sale by `seller`, returning it from custody.
+        fn unlist(&mut self, seller: &AccountId, id: TokenId) -> Result<(), Error> {
+            if self.custodial_listings.contains(id) {
+                let contract = self.env().account_id();
+                self.transfer_token_from(&contract, seller, id)?;
+            }
+            self.remove_listing(id)?;
+            self.env().emit_event(Unlisted {
+                seller: *seller,
+                id,
+                block: self.env().block_number(),
+                timestamp: self.env().block_timestamp(),
+            });
+            Ok(())
+        }
+
+        /// Publishes token `id` for sale by `seller`.
+        ///
+        /// Callers validate the listing before calling it, so nothing is written on error and
+        /// `Listed` is emitted only after the listing is stored.
+        fn add_listing(&mut self, seller: &AccountId, id: TokenId, price: Balance) -> Result<(), Error> {
+            self.store_listing(seller, id, price)?;
+            if self.lowest_listing_price.is_none_or(|lowest| price < lowest) {
+                self.lowest_listing_price = Some(price);
+            }
+            self.env().emit_event(Listed {
+                seller: *seller,
+                id,
+                price,
+                block: self.env().block_number(),
+                timestamp: self.env().block_timestamp(),
+            });
+            Ok(())
+        }
+
+        /// Stores listing of token `id` by `seller` without updating the floor or emitting an event.
+        fn store_listing(&mut self, seller: &AccountId, id: TokenId, price: Balance) -> Result<(), Error> {
+            if self.sale_index.contains(id) {
+                return Err(Error::AlreadyForSale);
+            }
+            self.tokens_for_sale.push(id);
+            self.sale_index.insert(id, &());
+            debug_assert!(
+                self.tokens_for_sale.iter().filter(|token| **token == id).count() == 1,
+                "token listed twice"
+            );
+            self.prices.insert(id, &price);
+            self.sellers.insert(id, seller);
+            let count = self.count_for_sale_by_owner(*seller).saturating_add(1);
+            self.listings_count_by_seller.insert(seller, &count);
+            Ok(())
+        }
+
+        /// Recomputes `lowest_listing_price` from public listings.
+        fn refresh_lowest_listing_price(&mut self) {
+            self.lowest_listing_price = self.public_listings().filter_map(|id| self.prices.get(id)).min();
+        }
+
+        /// Removes token `id` from sale.
+        fn remove_listing(&mut self, id: TokenId) -> Result<(), Error> {
+            let index = self.tokens_for_sale.iter().position(|token| *token == id).ok_or(Error::CannotFetchValue)?;
+            self.tokens_for_sale.remove(index);
+            self.sale_index.remove(id);
+            let price = self.prices.get(id);
+            self.prices.remove(id);
+            if self.private_buyers.contains(id) {
+                self.private_listings_count = self.private_listings_count.saturating_sub(1);
+                self.private_buyers.remove(id);
+            } else if price == self.lowest_listing_price {
+                self.refresh_lowest_listing_price();
+            }
+            if let Some(seller) = self.sellers.get(id) {
+                let count = self.count_for_sale_by_owner(seller).saturating_sub(1);
+                self.listings_count_by_seller.insert(seller, &count);
+            }
+            self.sellers.remove(id);
+            self.custodial_listings.remove(id);
+            self.private_buyers.remove(id);
+            self.held_listings.remove(id);
+            Ok(())
+        }
+
+        /// Removes offer of `offeror` for token `id`, recomputing the best offer if needed.
+        fn remove_offer(&mut self, id: TokenId, offeror: &AccountId) {
+            self.offers.remove((id, offeror));
+            let mut offerors = self.offerors.get(id).unwrap_or_default();
+            offerors.retain(|account| account != offeror);
+            self.offerors.insert(id, &offerors);
+
+            if self.best_offers.get(id).is_some_and(|(best, _)| best == *offeror) {
+                let best = offerors
+                    .iter()
+                    .filter_map(|account| self.offers.get((id, account)).map(|(amount, _)| (*account, amount)))
+                    .max_by_key(|(_, amount)| *amount);
+                match best {
+                    Some(best) => self.best_offers.insert(id, &best),
+                    None => self.best_offers.remove(id),
+                }
+            }
+        }
+
+        /// Appends sale to history of token `id`, dropping the oldest sales beyond `MAX_SALE_HISTORY`.
+        fn record_sale(&mut self, id: TokenId, seller: AccountId, buyer: AccountId, price: Balance) {
+            let mut history = self.sale_history.get(id).unwrap_or_default();
+            if history.len() >= MAX_SALE_HISTORY {
+                history.drain(..=history.len() - MAX_SALE_HISTORY);
+            }
+            history.push((seller, buyer, price, self.env().block_number()));
+            self.sale_history.insert(id, &history);
+
+            let slot = self.sales_count % MAX_RECENT_SALES;
+            self.recent_sales.insert(slot, &(id, seller, buyer, price, self.env().block_number()));
+            self.sales_count = self.sales_count.saturating_add(1);
+        }
+
+        /// Sets up storage of a new contract.
+        fn init(&mut self) {
+            let caller = Self::env().caller();
+            self.owner = caller;
+            self.minters.insert(caller, &());
+            self.next_token_id = 1;
+            self.fee_recipient = caller;
+            self.max_royalty_bps = DEFAULT_MAX_ROYALTY_BPS;
+            self.transfer_hook_gas_limit = DEFAULT_TRANSFER_HOOK_GAS_LIMIT;
+        }
+
+        /// Returns error if blacklist is enabled and `account` is blacklisted.
+        fn ensure_not_blacklisted(&self, account: &AccountId) -> Result<(), Error> {
+            if self.blacklist_enabled && self.blacklist.contains(account) {
+                return Err(Error::Blacklisted);
+            }
+            Ok(())
+        }
+
+        /// Returns error if caller cannot change metadata of token `id`.
+        fn ensure_can_edit_metadata(&self, id: TokenId) -> Result<(), Error> {
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            }
+            if self.creators.get(id) != Some(self.env().caller()) {
+                return Err(Error::NotAllowed);
+            }
+            if self.is_metadata_frozen(id) {
+                return Err(Error::MetadataFrozen);
+            }
+            Ok(())
+        }
+
+        /// Returns error if metadata of the collection is frozen.
+        fn ensure_collection_not_frozen(&self) -> Result<(), Error> {
+            if self.metadata_frozen {
+                return Err(Error::MetadataFrozen);
+            }
+            Ok(())
+        }
+
+        /// Returns error if token `id` was transferred less than `cooldown_blocks` ago.
+        fn ensure_cooldown_passed(&self, id: TokenId) -> Result<(), Error> {
+            if let (Some(cooldown), Some(last)) = (self.cooldown_blocks, self.last_transfer.get(id)) {
+                if self.env().block_number() < last.saturating_add(cooldown) {
+                    return Err(Error::CooldownActive);
+                }
+            }
+            Ok(())
+        }
+
+        /// Creates token `id` with `data` owned by `to`.
+        fn mint_token_to(&mut self, to: &AccountId, id: TokenId, data: &NftData) -> Result<(), Error> {
+            self.create_token(to, id, data)?;
+            self.env().emit_event(Transfer {
+                from: Some(AccountId::from([0x0; 32])),
+                to: Some(*to),
+                id,
+            });
+            Ok(())
+        }
+
+        /// Creates token `id` with `data` owned by `to` without emitting an event.
+        fn create_token(&mut self, to: &AccountId, id: TokenId, data: &NftData) -> Result<(), Error> {
+            self.register_token(to, id)?;
+            self.token_data.insert(id, data);
+            self.token_templates.remove(id);
+            Ok(())
+        }
+
+        /// Creates token `id` owned by `to` with metadata of template `template_id`.
+        fn mint_template_to(&mut self, to: &AccountId, id: TokenId, template_id: u32) -> Result<(), Error> {
+            self.register_token(to, id)?;
+            self.token_templates.insert(id, &template_id);
+            self.token_data.remove(id);
+            self.env().emit_event(Transfer {
+                from: Some(AccountId::from([0x0; 32])),
+                to: Some(*to),
+                id,
+            });
+            Ok(())
+        }
+
+        /// Records new token `id` owned by `to` and minted by the caller.
+        fn register_token(&mut self, to: &AccountId, id: TokenId) -> Result<(), Error> {
+            self.add_token_to(to, id)?;
+            self.all_tokens.push(id);
+            self.creators.insert(id, &self.env().caller());
+            self.last_transfer.insert(id, &self.env().block_number());
+            Ok(())
+        }
+
+        /// Returns own metadata of token `id`, or metadata of its template.
+        fn metadata_of(&self, id: TokenId) -> Option<NftData> {
+            self.token_data
+                .get(id)
+                .or_else(|| self.templates.get(self.token_templates.get(id)?))
+        }
+
+        /// Returns error if supply is locked or minting `count` more tokens exceeds the max supply.
+        fn ensure_can_mint(&self, count: u32) -> Result<(), Error> {
+            self.ensure_supply_unlocked()?;
+            if let Some(max_supply) = self.max_supply {
+                let supply = self.total_supply().checked_add(count).ok_or(Error::MaxSupplyReached)?;
+                if supply > max_supply {
+                    return Err(Error::MaxSupplyReached);
+                }
+            }
+            Ok(())
+        }
+
+        /// Removes token `id` from index of attribute `key` with `value`.
+        fn unindex_attribute(&mut self, id: TokenId, key: String, value: String) {
+            let mut ids = self.attribute_index.get((key.clone(), value.clone())).unwrap_or_default();
+            ids.retain(|token| *token != id);
+            if ids.is_empty() {
+                self.attribute_index.remove((key, value));
+            } else {
+                self.attribute_index.insert((key, value), &ids);
+            }
+        }
+
+        /// Removes all attributes of token `id` and their index entries.
+        fn clear_attributes(&mut self, id: TokenId) {
+            for key in self.attribute_keys.get(id).unwrap_or_default() {
+                if let Some(value) = self.attributes.get((id, key.clone())) {
+                    self.unindex_attribute(id, key.clone(), value);
+                }
+                self.attributes.remove((id, key));
+            }
+            self.attribute_keys.remove(id);
+        }
+
+        /// Returns account of the key which signed `message_hash`.
+        fn recover_signer(&self, signature: &[u8; 65], message_hash: &[u8; 32]) -> Result<AccountId, Error> {
+            let public_key = self
+                .env()
+                .ecdsa_recover(signature, message_hash)
+                .map_err(|_| Error::InvalidSignature)?;
+            Ok(AccountId::from(self.env().hash_bytes::<ink_env::hash::Blake2x256>(&public_key)))
+        }
+
+        /// Returns the hash of `voucher` bound to this contract.
+        fn hash_voucher(&self, voucher: &MintVoucher) -> [u8; 32] {
+            self.env().hash_encoded::<ink_env::hash::Blake2x256, _>(&(self.env().account_id(), voucher))
+        }
+
+        /// Returns error if minting is closed forever.
+        fn ensure_supply_unlocked(&self) -> Result<(), Error> {
+            if self.supply_locked {
+                return Err(Error::SupplyLocked);
+            }
+            Ok(())
+        }
+
+        /// Returns error if `account` reached the per account mint limit.
+        fn ensure_below_mint_limit(&self, account: &AccountId) -> Result<(), Error> {
+            match self.max_per_account {
+                Some(max_per_account) if self.minted_count_of(*account) >= max_per_account => {
+                    Err(Error::MintLimitReached)
+                }
+                _ => Ok(()),
+            }
+        }
+
+        /// Returns value sent above `fee`, error if the fee is not covered.
+        fn ensure_fee_paid(&self, fee: Balance) -> Result<Balance, Error> {
+            self.env()
+                .transferred_value()
+                .checked_sub(fee)
+                .ok_or(Error::NotEnoughSent)
+        }
+
+        /// Pays `fee` to the fee recipient and refunds `excess` to `payer`.
+        fn pay_fee(&mut self, payer: &AccountId, fee: Balance, excess: Balance) -> Result<(), Error> {
+            if fee > 0 {
+                self.pay(self.fee_recipient, fee)?;
+            }
+            if excess > 0 {
+                self.pay(*payer, excess)?;
+            }
+            Ok(())
+        }
+
+        /// Credits the mint fee to revenue shares if set, pays it to the fee recipient otherwise,
+        /// and refunds `excess` to `payer`.
+        fn collect_mint_fee(&mut self, payer: &AccountId, excess: Balance) -> Result<(), Error> {
+            if self.revenue_shares.is_empty() || self.mint_fee == 0 {
+                return self.pay_fee(payer, self.mint_fee, excess);
+            }
+            let mut credits = Vec::new();
+            let mut remaining = self.mint_fee;
+            for (i, (recipient, bps)) in self.revenue_shares.iter().enumerate() {
+                // the last recipient takes the rounding remainder
+                let share = if i + 1 == self.revenue_shares.len() {
+                    remaining
+                } else {
+                    checked_mul_bps(self.mint_fee, *bps)?
+                };
+                remaining = checked_sub(remaining, share)?;
+                credits.push((*recipient, checked_add(self.pending_withdrawal_of(*recipient), share)?));
+            }
+            if excess > 0 {
+                self.pay(*payer, excess)?;
+            }
+            for (recipient, pending) in credits {
+                self.pending_withdrawals.insert(recipient, &pending);
+            }
+            Ok(())
+        }
+
+        /// Counts a mint of `account` towards the per account limit.
+        fn record_public_mint(&mut self, account: &AccountId) {
+            let minted = self.minted_count_of(*account).saturating_add(1);
+            self.minted_count.insert(account, &minted);
+        }
+
+        /// Returns the next free sequential id and advances the counter.
+        fn take_next_token_id(&mut self) -> Result<TokenId, Error> {
+            let id = self.peek_next_token_id()?;
+            self.next_token_id = id.checked_add(1).ok_or(Error::CannotInsert)?;
+            Ok(id)
+        }
+
+        /// Returns the next free sequential id without advancing the counter.
+        fn peek_next_token_id(&self) -> Result<TokenId, Error> {
+            let mut id = self.next_token_id;
+            while self.exists(id) {
+                id = id.checked_add(1).ok_or(Error::CannotInsert)?;
+            }
+            Ok(id)
+        }
+
+        /// Calls `transfer_from` of ERC-721 `contract` for token `id`.
+        #[cfg(not(test))]
+        fn external_transfer_from(
+            &self,
+            contract: AccountId,
+            from: AccountId,
+            to: AccountId,
+            id: u32,
+        ) -> Result<(), Error> {
+            use ink_env::call::{build_call, Call, ExecutionInput, Selector};
+
+            build_call::<Environment>()
+                .call_type(Call::new().callee(contract))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink_lang::selector_bytes!("transfer_from")))
+                        .push_arg(from)
+                        .push_arg(to)
+                        .push_arg(id),
+                )
+                .returns::<Result<(), u8>>()
+                .fire()
+                .map_err(|_| Error::CannotTransferToken)?
+                .map_err(|_| Error::CannotTransferToken)
+        }
+
+        /// Calls `transfer_from` of the mock ERC-721 `contract`, the off-chain environment
+        /// does not support cross-contract calls.
+        #[cfg(test)]
+        fn external_transfer_from(
+            &self,
+            contract: AccountId,
+            from: AccountId,
+            to: AccountId,
+            id: u32,
+        ) -> Result<(), Error> {
+            tests::ExternalErc721Mock::transfer_from(contract, from, to, id)
+        }
+
+        /// Calls `on_transfer` of `hook` contract with bounded gas, ignoring its failure so a broken
+        /// or gas burning hook cannot block transfers.
+        #[cfg(not(test))]
+        fn notify_transfer_hook(&self, hook: AccountId, from: AccountId, to: AccountId, id: TokenId) {
+            use ink_env::call::{build_call, Call, ExecutionInput, Selector};
+
+            let _ = build_call::<Environment>()
+                .call_type(Call::new().callee(hook).gas_limit(self.transfer_hook_gas_limit))
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink_lang::selector_bytes!("on_transfer")))
+                        .push_arg(from)
+                        .push_arg(to)
+                        .push_arg(id),
+                )
+                .returns::<()>()
+                .fire();
+        }
+
+        /// Calls `on_transfer` of the mock `hook` contract, the off-chain environment
+        /// does not support cross-contract calls.
+        #[cfg(test)]
+        fn notify_transfer_hook(&self, hook: AccountId, from: AccountId, to: AccountId, id: TokenId) {
+            let _ = tests::TransferHookMock::on_transfer(hook, from, to, id);
+        }
+
+        /// Moves `amount` of PSP22 `token` from `from` to `to`, with `transfer` if `from` is the contract
+        /// and `transfer_from` within allowance of the contract otherwise.
+        #[cfg(not(test))]
+        fn psp22_transfer_from(&self, token: AccountId, from: AccountId, to: AccountId, amount: Balance) -> Result<(), Error> {
+            use ink_env::call::{build_call, Call, ExecutionInput, Selector};
+
+            let call = build_call::<Environment>().call_type(Call::new().callee(token));
+            let result = if from == self.env().account_id() {
+                call.exec_input(
+                    ExecutionInput::new(Selector::new(ink_lang::selector_bytes!("PSP22::transfer")))
+                        .push_arg(to)
+                        .push_arg(amount)
+                        .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<Result<(), u8>>()
+                .fire()
+            } else {
+                call.exec_input(
+                    ExecutionInput::new(Selector::new(ink_lang::selector_bytes!("PSP22::transfer_from")))
+                        .push_arg(from)
+                        .push_arg(to)
+                        .push_arg(amount)
+                        .push_arg(Vec::<u8>::new()),
+                )
+                .returns::<Result<(), u8>>()
+                .fire()
+            };
+            result
+                .map_err(|_| Error::CannotMakeTransfer)?
+                .map_err(|_| Error::CannotMakeTransfer)
+        }
+
+        /// Moves `amount` of the mock PSP22 `token` from `from` to `to`, the off-chain environment
+        /// does not support cross-contract calls.
+        #[cfg(test)]
+        fn psp22_transfer_from(&self, token: AccountId, from: AccountId, to: AccountId, amount: Balance) -> Result<(), Error> {
+            tests::Psp22Mock::transfer(token, from, to, amount)
+        }
+
+        /// Removes token `id` from the owner.
+        fn remove_token_from(&mut self, from: &AccountId, id: TokenId) -> Result<(), Error> {
+            if !self.exists(id) {
+                return Err(Error::TokenNotFound);
+            }
+
+            let Self {
+                token_owner,
+                owned_tokens,
+                owned_tokens_count,
+                ..
+            } = self;
+
+            let count = owned_tokens_count
+                .get(from)
+                .and_then(|c| c.checked_sub(1))
+                .ok_or(Error::CannotFetchValue)?;
+            let mut tokens = owned_tokens
+                .get(from)
+                .ok_or(Error::CannotFetchValue)?;
+            let index = tokens.iter().position(|token| *token == id).ok_or(Error::CannotFetchValue)?;
+            tokens.remove(index);
+            debug_assert_eq!(tokens.len() as u32, count, "owned tokens out of sync with their count");
+
+            owned_tokens_count.insert(from, &count);
+            owned_tokens.insert(from, &tokens);
+            token_owner.remove(id);
+
+            Ok(())
+        }
+
+        /// Adds the token `id` to the `to` AccountID.
+        fn add_token_to(&mut self, to: &AccountId, id: TokenId) -> Result<(), Error> {
+            let Self {
+                token_owner,
+                owned_tokens,
+                owned_tokens_count,
+                ..
+            } = self;
+
+            if token_owner.contains(id) {
+                return Err(Error::TokenExists);
+            }
+
+            if *to == AccountId::from([0x0; 32]) {
+                return Err(Error::NotAllowed);
+            };
+
+            let count = owned_tokens_count.get(to).map(|c| c + 1).unwrap_or(1);
+            let mut tokens = owned_tokens
+                .get(to)
+                .unwrap_or_default();
+            tokens.push(id);
+            debug_assert_eq!(tokens.len() as u32, count, "owned tokens out of sync with their count");
+
+            owned_tokens_count.insert(to, &count);
+            owned_tokens.insert(to, &tokens);
+
+            token_owner.insert(id, to);
+
+            Ok(())
+        }
+
+        // Returns the total number of tokens from an account.
+        fn balance_of_or_zero(&self, of: &AccountId) -> u32 {
+            self.owned_tokens_count.get(of).unwrap_or(0)
+        }
+
+        /// Returns true if the `AccountId` `from` is the owner of token `id`
+        fn is_owner_of(&self, from: Option<AccountId>, id: TokenId) -> bool {
+            let owner = self.owner_of(id);
+            from != Some(AccountId::from([0x0; 32])) && (from == owner)
+        }
+
+        /// Returns true if token `id` exists or false if it does not.
+        fn exists(&self, id: TokenId) -> bool {
+            self.token_owner.contains(id)
+        }
+
+        /// Returns error if caller is not the owner of the collection.
+        fn ensure_owner(&self) -> Result<(), Error> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            }
+            Ok(())
+        }
+
+        /// Returns error if `price` is zero and free listings are not allowed, or below the floor price.
+        fn ensure_valid_price(&self, price: Balance) -> Result<(), Error> {
+            if price == 0 && !self.allow_free_listings {
+                return Err(Error::InvalidPrice);
+            }
+            self.ensure_above_floor(price)
+        }
+
+        /// Returns error if `price` is below the floor price.
+        fn ensure_above_floor(&self, price: Balance) -> Result<(), Error> {
+            match self.floor_price {
+                Some(floor) if price < floor => Err(Error::BelowFloor),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// Encodes `bytes` with the bitcoin base58 alphabet used by IPFS.
+    fn base58(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+        // base58 digits in little endian order
+        let mut digits: Vec<u8> = Vec::new();
+        for byte in bytes {
+            let mut carry = u32::from(*byte);
+            for digit in digits.iter_mut() {
+                carry += u32::from(*digit) << 8;
+                *digit = (carry % 58) as u8;
+                carry /= 58;
+            }
+            while carry > 0 {
+                digits.push((carry % 58) as u8);
+                carry /= 58;
+            }
+        }
+        let zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+        core::iter::repeat_n(ALPHABET[0], zeros)
+            .chain(digits.iter().rev().map(|digit| ALPHABET[*digit as usize]))
+            .map(char::from)
+            .collect()
+    }
+
+    /// Returns `bps` basis points of `amount` rounded down, error if the result overflows.
+    fn checked_mul_bps(amount: Balance, bps: u16) -> Result<Balance, Error> {
+        let bps = Balance::from(bps);
+        let max_bps = Balance::from(MAX_BPS);
+        // remainder is below `MAX_BPS`, so its product with `u16` basis points cannot overflow
+        let whole = (amount / max_bps).checked_mul(bps).ok_or(Error::Overflow)?;
+        checked_add(whole, amount % max_bps * bps / max_bps)
+    }
+
+    /// Returns `a + b`, error if it overflows.
+    fn checked_add(a: Balance, b: Balance) -> Result<Balance, Error> {
+        a.checked_add(b).ok_or(Error::Overflow)
+    }
+
+    /// Returns `a - b`, error if it underflows.
+    fn checked_sub(a: Balance, b: Balance) -> Result<Balance, Error> {
+        a.checked_sub(b).ok_or(Error::Overflow)
+    }
+
+    /// Returns error if basis points of royalty recipients sum to more than `MAX_BPS`.
+    fn ensure_valid_royalty(recipients: &[(AccountId, u16)]) -> Result<(), Error> {
+        let total: u32 = recipients.iter().map(|(_, bps)| u32::from(*bps)).sum();
+        if total > u32::from(MAX_BPS) {
+            return Err(Error::InvalidRoyalty);
+        }
+        Ok(())
+    }
+
+    impl crate::Erc721Core for Erc721 {
+        #[ink(message)]
+        fn owner_of(&self, id: TokenId) -> Option<AccountId> {
+            Erc721::owner_of(self, id)
+        }
+
+        #[ink(message)]
+        fn balance_of(&self, owner: AccountId) -> u32 {
+            Erc721::balance_of(self, owner)
+        }
+
+        #[ink(message, payable)]
+        fn transfer_from(&mut self, from: AccountId, to: AccountId, id: TokenId) -> Result<(), Error> {
+            Erc721::transfer_from(self, from, to, id)
+        }
+
+        #[ink(message)]
+        fn approve(&mut self, to: AccountId, id: TokenId) -> Result<(), Error> {
+            Erc721::approve(self, to, id)
+        }
+    }
+
+    /// Unit tests
+    #[cfg(test)]
+    // the original tests predate the clippy gate and are kept as written
+    #[allow(unused_variables, clippy::bool_assert_comparison)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+
+        type Event = <Erc721 as ::ink_lang::reflect::ContractEventBase>::Type;
+
+        #[ink_lang::test]
+        fn mint_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            // Create a new contract instance.
+            let mut erc721 = Erc721::new();
+            // Token 1 does not exists.
+            assert_eq!(erc721.owner_of(1), None);
+            // Alice does not owns tokens.
+            assert_eq!(erc721.balance_of(accounts.alice), 0);
+            // Create token Id 1.
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            // Alice owns 1 token.
+            assert_eq!(erc721.balance_of(accounts.alice), 1);
+        }
+        
+        #[ink_lang::test]
+        fn mint_from_template_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            let edition = NftData { poebat: Some("ipfs://edition".to_string()) };
+            assert_eq!(erc721.create_template(NftData { poebat: Some("ipfs://edition".to_string()) }), Ok(0));
+            assert_eq!(erc721.get_template(0), Some(NftData { poebat: Some("ipfs://edition".to_string()) }));
+            assert_eq!(erc721.mint_from_template(1, 1), Err(Error::TemplateNotFound));
+
+            assert_eq!(erc721.mint_from_template(1, 0), Ok(()));
+            assert_eq!(erc721.mint_from_template(2, 0), Ok(()));
+            assert_eq!(erc721.mint_from_template(2, 0), Err(Error::TokenExists));
+            assert_eq!(erc721.token_data.get(1), None);
+            assert_eq!(erc721.get_nft_info(1), Ok(edition));
+            assert_eq!(erc721.get_nft_info(1), erc721.get_nft_info(2));
+            assert_eq!(erc721.token_uri(2), Some("ipfs://edition".to_string()));
+            assert_eq!(erc721.tokens_of_owner(accounts.alice), vec![1, 2]);
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.create_template(NftData { poebat: None }), Err(Error::NotAllowed));
+        }
+
+        #[ink_lang::test]
+        fn next_token_id_works() {
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.next_token_id(), 1);
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Ok(1));
+            assert_eq!(erc721.next_token_id(), 2);
+
+            // explicitly minted id is skipped
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.next_token_id(), 3);
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Ok(3));
+        }
+
+        #[ink_lang::test]
+        fn reserve_mint_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            // explicitly minted id is skipped by sequential mint
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(erc721.reserve_mint(accounts.charlie, 5), Ok(vec![1, 3, 4, 5, 6]));
+            assert_eq!(erc721.balance_of(accounts.charlie), 5);
+            assert_eq!(erc721.tokens_of_owner(accounts.charlie), vec![1, 3, 4, 5, 6]);
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Ok(7));
+            assert_eq!(erc721.total_supply(), 7);
+
+            // only minters
+            set_caller(accounts.bob);
+            assert_eq!(erc721.reserve_mint(accounts.bob, 1), Err(Error::NotAllowed));
+        }
+
+        #[ink_lang::test]
+        fn reserve_mint_respects_max_supply() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_max_supply(Some(4)), Ok(()));
+            assert_eq!(erc721.reserve_mint(accounts.charlie, 3), Ok(vec![1, 2, 3]));
+
+            // whole batch is rejected
+            assert_eq!(erc721.reserve_mint(accounts.charlie, 2), Err(Error::MaxSupplyReached));
+            assert_eq!(erc721.balance_of(accounts.charlie), 3);
+
+            assert_eq!(erc721.mint(10, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(11, NftData { poebat: None }), Err(Error::MaxSupplyReached));
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Err(Error::MaxSupplyReached));
+        }
+
+        #[ink_lang::test]
+        fn max_per_account_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_max_per_account(Some(2)), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_max_per_account(None), Err(Error::NotOwner));
+            // under the limit
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Ok(2));
+            assert_eq!(erc721.minted_count_of(accounts.bob), 2);
+            // at the limit
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Err(Error::MintLimitReached));
+            // limit counts lifetime mints, not current balance
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Err(Error::MintLimitReached));
+            assert_eq!(erc721.balance_of(accounts.bob), 1);
+
+            // other accounts are not affected
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+        }
+
+        #[ink_lang::test]
+        fn rebuild_index_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            // simulate desync
+            erc721.all_tokens.retain(|id| *id != 1);
+            assert_eq!(erc721.total_supply(), 1);
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.rebuild_index(vec![1]), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+
+            // missing entry is re-added, existing and unknown ids are skipped
+            assert_eq!(erc721.rebuild_index(vec![1, 2, 3]), Ok(()));
+            assert_eq!(erc721.all_tokens, vec![2, 1]);
+            // idempotent
+            assert_eq!(erc721.rebuild_index(vec![1, 2]), Ok(()));
+            assert_eq!(erc721.all_tokens, vec![2, 1]);
+        }
+
+        #[ink_lang::test]
+        fn approved_operators_of_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.approved_operators_of(accounts.alice), vec![]);
+
+            assert_eq!(erc721.set_approval_for_all(accounts.bob, true), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(accounts.charlie, true), Ok(()));
+            // approving again does not list the operator twice
+            assert_eq!(erc721.set_approval_for_all(accounts.bob, true), Ok(()));
+            assert_eq!(erc721.approved_operators_of(accounts.alice), vec![accounts.bob, accounts.charlie]);
+
+            assert_eq!(erc721.set_approval_for_all(accounts.bob, false), Ok(()));
+            assert_eq!(erc721.approved_operators_of(accounts.alice), vec![accounts.charlie]);
+            assert!(!erc721.is_approved_for_all(accounts.alice, accounts.bob));
+
+            assert_eq!(erc721.set_approval_for_all(accounts.charlie, false), Ok(()));
+            assert_eq!(erc721.approved_operators_of(accounts.alice), vec![]);
+        }
+
+        #[ink_lang::test]
+        fn is_approved_or_owner_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert!(erc721.is_approved_or_owner(accounts.alice, 1));
+            assert!(!erc721.is_approved_or_owner(accounts.bob, 1));
+
+            assert_eq!(erc721.approve(accounts.bob, 1), Ok(()));
+            assert!(erc721.is_approved_or_owner(accounts.bob, 1));
+
+            assert_eq!(erc721.set_approval_for_all(accounts.charlie, true), Ok(()));
+            assert!(erc721.is_approved_or_owner(accounts.charlie, 1));
+
+            assert!(!erc721.is_approved_or_owner(accounts.django, 1));
+            assert!(!erc721.is_approved_or_owner(accounts.alice, 2));
+        }
+
+        #[ink_lang::test]
+        fn publish_for_sale_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            // Create a new contract instance.
+            let mut erc721 = Erc721::new();
+
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 1, price: 10, on_hold: false}]);
+
+            assert_eq!(erc721.publish_for_sale(2, 100), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 1, price: 10, on_hold: false}, ForSale{id: 2, price: 100, on_hold: false}]);
+
+            assert_eq!(erc721.remove_from_sale(1), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 2, price: 100, on_hold: false}]);
+        }
+
+        #[ink_lang::test]
+        fn zero_price_listing_fails() {
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 0), Err(Error::InvalidPrice));
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            assert_eq!(erc721.update_price(1, 0), Err(Error::InvalidPrice));
+            assert_eq!(erc721.price_of(1), Ok(10));
+        }
+
+        #[ink_lang::test]
+        fn free_listing_works_when_allowed() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new_configured(Config {
+                allow_free_listings: true,
+                ..Default::default()
+            });
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 0), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.buy_nft(1), Ok(0));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+        }
+
+        #[ink_lang::test]
+        fn publish_batch_for_sale_works() {
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            for id in 1..=3 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            let events_before = ink_env::test::recorded_events().count();
+
+            assert_eq!(erc721.publish_batch_for_sale(vec![(1, 10), (2, 20), (3, 30)]), Ok(()));
+            assert_eq!(
+                erc721.get_tokens_for_sale(),
+                vec![
+                    ForSale { id: 1, price: 10, on_hold: false },
+                    ForSale { id: 2, price: 20, on_hold: false },
+                    ForSale { id: 3, price: 30, on_hold: false },
+                ]
+            );
+            let listed = ink_env::test::recorded_events()
+                .skip(events_before)
+                .map(|event| <Event as scale::Decode>::decode(&mut &event.data[..]).expect("invalid event"))
+                .filter_map(|event| match event {
+                    Event::Listed(Listed { id, price, .. }) => Some((id, price)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(listed, vec![(1, 10), (2, 20), (3, 30)]);
+        }
+
+        #[ink_lang::test]
+        fn publish_batch_for_sale_reverts_whole_batch() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            for id in 1..=4 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            assert_eq!(erc721.transfer(accounts.bob, 4), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 20), Ok(()));
+
+            assert_eq!(erc721.publish_batch_for_sale(vec![(1, 10), (2, 25), (3, 30)]), Err(Error::AlreadyForSale));
+            assert_eq!(erc721.publish_batch_for_sale(vec![(1, 10), (4, 40)]), Err(Error::NotApproved));
+            assert_eq!(erc721.publish_batch_for_sale(vec![(1, 10), (1, 15)]), Err(Error::AlreadyForSale));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale { id: 2, price: 20, on_hold: false }]);
+            assert_eq!(erc721.price_of(1), Err(Error::NotForSale));
+        }
+
+        #[ink_lang::test]
+        fn failed_publish_for_sale_leaves_no_listing() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            let events_before = ink_env::test::recorded_events().count();
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.publish_for_sale(1, 10), Err(Error::NotApproved));
+            assert_eq!(erc721.publish_for_sale(2, 10), Err(Error::TokenNotFound));
+            assert_eq!(erc721.prices.get(1), None);
+            assert_eq!(erc721.seller_of(1), None);
+            assert_eq!(erc721.get_tokens_for_sale(), vec![]);
+            assert_eq!(erc721.floor_listing_price(), None);
+            assert_eq!(ink_env::test::recorded_events().count(), events_before);
+        }
+
+        #[ink_lang::test]
+        fn floor_listing_price_works() {
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.floor_listing_price(), None);
+            for id in 1..=3 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+
+            assert_eq!(erc721.publish_for_sale(1, 50), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 20), Ok(()));
+            assert_eq!(erc721.publish_for_sale(3, 30), Ok(()));
+            assert_eq!(erc721.floor_listing_price(), Some(20));
+
+            // removing the floor listing falls back to the next lowest
+            assert_eq!(erc721.remove_from_sale(2), Ok(()));
+            assert_eq!(erc721.floor_listing_price(), Some(30));
+
+            // raising the floor price moves the floor, lowering another price replaces it
+            assert_eq!(erc721.update_price(3, 60), Ok(()));
+            assert_eq!(erc721.floor_listing_price(), Some(50));
+            assert_eq!(erc721.update_price(3, 5), Ok(()));
+            assert_eq!(erc721.floor_listing_price(), Some(5));
+
+            assert_eq!(erc721.remove_from_sale(3), Ok(()));
+            assert_eq!(erc721.remove_from_sale(1), Ok(()));
+            assert_eq!(erc721.floor_listing_price(), None);
+        }
+
+        #[ink_lang::test]
+        fn detailed_listings_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.set_fee_bps(250), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: Some("ipfs://one".to_string()) }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_royalty(1, vec![(accounts.charlie, 300), (accounts.django, 200)]), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 100), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 200), Ok(()));
+            // the listing of 2 goes stale
+            assert_eq!(erc721.transfer(accounts.bob, 2), Ok(()));
+
+            let listings = erc721.detailed_listings(0, 10);
+            assert_eq!(listings.len(), 1);
+            let listing = &listings[0];
+            assert_eq!(listing.id, 1);
+            assert_eq!(Some(listing.seller), erc721.seller_of(1));
+            assert_eq!(Ok(listing.price), erc721.price_of(1));
+            assert_eq!(Ok(&listing.metadata), erc721.get_nft_info(1).as_ref());
+            assert_eq!(listing.royalty_bps, 500);
+            assert_eq!(erc721.royalty_info(1, MAX_BPS as Balance), 500);
+            assert_eq!(listing.fee_bps, erc721.fee_bps());
+            assert_eq!(erc721.detailed_listings(1, 10), vec![]);
+        }
+
+        #[ink_lang::test]
+        fn get_listings_paged_works() {
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            for id in 1..=5 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+                assert_eq!(erc721.publish_for_sale(id, id as Balance * 10), Ok(()));
+            }
+            assert_eq!(erc721.listings_count(), 5);
+
+            // partial page
+            assert_eq!(erc721.get_listings_paged(1, 2), vec![ForSale{id: 2, price: 20, on_hold: false}, ForSale{id: 3, price: 30, on_hold: false}]);
+            assert_eq!(erc721.get_listings_paged(4, 10), vec![ForSale{id: 5, price: 50, on_hold: false}]);
+            // offset beyond the end
+            assert_eq!(erc721.get_listings_paged(5, 10), vec![]);
+        }
+
+        #[ink_lang::test]
+        fn get_listings_paged_clamps_limit() {
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            for id in 1..=TokenId::from(MAX_PAGE_SIZE + 1) {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+                assert_eq!(erc721.publish_for_sale(id, 10), Ok(()));
+            }
+            assert_eq!(erc721.get_listings_paged(0, u32::MAX).len(), MAX_PAGE_SIZE as usize);
+            assert_eq!(erc721.get_listings_paged(MAX_PAGE_SIZE, u32::MAX).len(), 1);
+        }
+
+        #[ink_lang::test]
+        fn cancel_all_listings_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            assert_eq!(erc721.publish_for_sale_custodial(2, 20), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(3, 30), Ok(()));
+
+            set_caller(accounts.alice);
+            let events_before = ink_env::test::recorded_events().count();
+            assert_eq!(erc721.cancel_all_listings(), Ok(2));
+            // other seller listing untouched
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 3, price: 30, on_hold: false}]);
+            assert_eq!(erc721.owner_of(2), Some(accounts.alice));
+            // custody return transfer and two unlisted events
+            assert_eq!(ink_env::test::recorded_events().count(), events_before + 3);
+            assert_eq!(erc721.cancel_all_listings(), Ok(0));
+        }
+
+        #[ink_lang::test]
+        fn buy_nft_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 1, price: 10, on_hold: false}]);
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(erc721.is_owner_of(Some(accounts.alice), 1), true);
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(accounts.bob, 10);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+            
+            assert_eq!(erc721.get_tokens_for_sale(), vec![]);
+            assert_eq!(erc721.is_owner_of(Some(accounts.bob), 1), true);
+
+        }
+
+        #[ink_lang::test]
+        fn buy_nft_overpayment_is_refunded() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint_to(accounts.django, 1, NftData { poebat: None }), Ok(()));
+            set_caller(accounts.django);
+            assert_eq!(erc721.approve_and_list(1, 100), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(150);
+            assert_eq!(erc721.buy_nft(1), Ok(100));
+            assert_eq!(get_balance(accounts.bob), 1_050);
+            assert_eq!(get_balance(accounts.django), 100);
+        }
+
+        #[ink_lang::test]
+        fn sales_since_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            for id in 1..=4 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+                assert_eq!(erc721.publish_for_sale(id, id * 10), Ok(()));
+            }
+            assert_eq!(erc721.sales_since(0, 10), vec![]);
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+            advance_blocks(1);
+            let later = ink_env::block_number::<ink_env::DefaultEnvironment>();
+            assert_eq!(erc721.buy_nft(2), Ok(20));
+            assert_eq!(erc721.buy_nft(3), Ok(30));
+            assert_eq!(erc721.buy_nft(4), Ok(40));
+
+            assert_eq!(erc721.sales_since(0, 10).len(), 4);
+            assert_eq!(
+                erc721.sales_since(later, 2),
+                vec![
+                    (2, accounts.alice, accounts.bob, 20, later),
+                    (3, accounts.alice, accounts.bob, 30, later),
+                ]
+            );
+            assert_eq!(erc721.sales_since(later + 1, 10), vec![]);
+        }
+
+        #[ink_lang::test]
+        fn split_royalty_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+
+            // split over 100% is rejected
+            assert_eq!(
+                erc721.set_royalty(1, vec![(accounts.charlie, 6_000), (accounts.django, 5_000)]),
+                Err(Error::InvalidRoyalty)
+            );
+            assert_eq!(erc721.set_royalty(1, vec![(accounts.charlie, 500), (accounts.django, 500)]), Ok(()));
+            assert_eq!(erc721.royalty_recipients(1), vec![(accounts.charlie, 500), (accounts.django, 500)]);
+            assert_eq!(erc721.royalty_info(1, 1_000), 100);
+
+            // only creator can set royalty
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_royalty(1, vec![]), Err(Error::NotAllowed));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.publish_for_sale(1, 1_000), Ok(()));
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(1_000);
+            assert_eq!(erc721.buy_nft(1), Ok(1_000));
+
+            assert_eq!(get_balance(accounts.charlie), 1_050);
+            assert_eq!(get_balance(accounts.django), 50);
+        }
+
+        #[ink_lang::test]
+        fn royalties_earned_accumulate() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            for id in 1..=2 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+                assert_eq!(erc721.set_royalty(id, vec![(accounts.charlie, 500)]), Ok(()));
+            }
+            assert_eq!(erc721.royalties_earned_of(accounts.charlie), 0);
+            assert_eq!(erc721.publish_for_sale(1, 1_000), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 2_000), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(1_000);
+            assert_eq!(erc721.buy_nft(1), Ok(1_000));
+            assert_eq!(erc721.royalties_earned_of(accounts.charlie), 50);
+
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(2_000);
+            assert_eq!(erc721.buy_nft(2), Ok(2_000));
+            assert_eq!(erc721.royalties_earned_of(accounts.charlie), 150);
+            assert_eq!(erc721.royalties_earned_of(accounts.alice), 0);
+        }
+
+        #[ink_lang::test]
+        fn mint_with_royalty_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+
+            assert_eq!(erc721.mint_with_royalty(1, NftData { poebat: None }, accounts.charlie, 250), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+            assert_eq!(erc721.creator_of(1), Some(accounts.alice));
+            assert_eq!(erc721.royalty_recipients(1), vec![(accounts.charlie, 250)]);
+
+            // bad bps leaves no token minted
+            assert_eq!(
+                erc721.mint_with_royalty(2, NftData { poebat: None }, accounts.charlie, MAX_BPS + 1),
+                Err(Error::InvalidRoyalty)
+            );
+            assert_eq!(erc721.owner_of(2), None);
+            assert_eq!(erc721.get_nft_info(2), Err(Error::TokenNotFound));
+            assert_eq!(erc721.balance_of(accounts.alice), 1);
+        }
+
+        #[ink_lang::test]
+        fn dutch_auction_price_declines() {
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.dutch_price(1), Err(Error::NotForSale));
+
+            assert_eq!(erc721.start_dutch_auction(1, 100, 200, 10), Err(Error::NotAllowed));
+            assert_eq!(erc721.start_dutch_auction(1, 1_000, 100, 10), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Err(Error::AlreadyForSale));
+            // start
+            assert_eq!(erc721.dutch_price(1), Ok(1_000));
+            // midpoint
+            advance_blocks(5);
+            assert_eq!(erc721.dutch_price(1), Ok(550));
+            // end and after
+            advance_blocks(5);
+            assert_eq!(erc721.dutch_price(1), Ok(100));
+            advance_blocks(5);
+            assert_eq!(erc721.dutch_price(1), Ok(100));
+        }
+
+        #[ink_lang::test]
+        fn buy_dutch_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.start_dutch_auction(1, 1_000, 100, 10), Ok(()));
+            advance_blocks(5);
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(500);
+            assert_eq!(erc721.buy_dutch(1), Err(Error::NotEnoughSent));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(600);
+            assert_eq!(erc721.buy_dutch(1), Ok(()));
+
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            // overpayment is refunded
+            assert_eq!(get_balance(accounts.bob), 1_050);
+            assert_eq!(erc721.dutch_price(1), Err(Error::NotForSale));
+        }
+
+        #[ink_lang::test]
+        fn dutch_auction_with_zero_end_price_fails() {
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.start_dutch_auction(1, 1_000, 0, 10), Err(Error::InvalidPrice));
+            assert_eq!(erc721.dutch_price(1), Err(Error::NotForSale));
+            assert_eq!(erc721.start_dutch_auction(1, 1_000, 1, 10), Ok(()));
+        }
+
+        #[ink_lang::test]
+        fn cancel_dutch_auction_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.cancel_dutch_auction(1), Err(Error::NotForSale));
+            assert_eq!(erc721.start_dutch_auction(1, 1_000, 100, 10), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.cancel_dutch_auction(1), Err(Error::NotApproved));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.cancel_dutch_auction(1), Ok(()));
+            assert_eq!(erc721.dutch_price(1), Err(Error::NotForSale));
+        }
+
+        #[ink_lang::test]
+        fn transfer_ends_dutch_auction() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.start_dutch_auction(1, 1_000, 100, 10), Ok(()));
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.dutch_price(1), Err(Error::NotForSale));
+
+            // the old sale does not revive when the token comes back to the seller
+            set_caller(accounts.bob);
+            assert_eq!(erc721.transfer(accounts.alice, 1), Ok(()));
+            set_caller(accounts.charlie);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(1_000);
+            assert_eq!(erc721.buy_dutch(1), Err(Error::NotForSale));
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+        }
+
+        #[ink_lang::test]
+        fn new_owner_can_list_despite_stale_dutch_auction() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint_to(accounts.bob, 1, NftData { poebat: None }), Ok(()));
+            // entry of a previous owner left behind before transfers cleared it
+            erc721.dutch_auctions.insert(1, &DutchAuction {
+                seller: accounts.alice,
+                start_price: 1_000,
+                end_price: 100,
+                start_block: 0,
+                duration: 10,
+            });
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            assert_eq!(erc721.remove_from_sale(1), Ok(()));
+            assert_eq!(erc721.start_dutch_auction(1, 1_000, 100, 10), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Err(Error::AlreadyForSale));
+        }
+
+        #[ink_lang::test]
+        fn sale_stats_work() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 30), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 20), Ok(()));
+            assert_eq!(erc721.highest_sale(), None);
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(30);
+            assert_eq!(erc721.buy_nft(1), Ok(30));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(20);
+            assert_eq!(erc721.buy_nft(2), Ok(20));
+
+            assert_eq!(erc721.total_volume(), 50);
+            assert_eq!(erc721.highest_sale(), Some((1, 30)));
+        }
+
+        #[ink_lang::test]
+        fn sale_history_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.sale_history_of(1), vec![]);
+
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+
+            advance_blocks(2);
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 15), Ok(()));
+            set_caller(accounts.charlie);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(15);
+            assert_eq!(erc721.buy_nft(1), Ok(15));
+
+            assert_eq!(
+                erc721.sale_history_of(1),
+                vec![(accounts.alice, accounts.bob, 10, 0), (accounts.bob, accounts.charlie, 15, 2)]
+            );
+        }
+
+        #[ink_lang::test]
+        fn sale_history_is_bounded() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            for price in 0..(MAX_SALE_HISTORY as Balance + 2) {
+                erc721.record_sale(1, accounts.alice, accounts.bob, price);
+            }
+            let history = erc721.sale_history_of(1);
+            assert_eq!(history.len(), MAX_SALE_HISTORY);
+            // oldest sales are dropped
+            assert_eq!(history[0].2, 2);
+            assert_eq!(history[MAX_SALE_HISTORY - 1].2, MAX_SALE_HISTORY as Balance + 1);
+        }
+
+        #[ink_lang::test]
+        fn approval_of_other_operator_does_not_allow_listing() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint_to(accounts.bob, 1, NftData { poebat: None }), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_approval_for_all(accounts.charlie, true), Ok(()));
+            assert_eq!(erc721.approve(accounts.django, 1), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Err(Error::MarketplaceNotApproved));
+            assert_eq!(erc721.price_of(1), Err(Error::NotForSale));
+        }
+
+        #[ink_lang::test]
+        fn publish_for_sale_requires_marketplace_approval() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+
+            // without approval
+            assert_eq!(erc721.publish_for_sale(1, 10), Err(Error::MarketplaceNotApproved));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![]);
+
+            // approved for one token
+            assert_eq!(erc721.approve(contract, 2), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 20), Ok(()));
+
+            // approved for all tokens
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 2, price: 20, on_hold: false}, ForSale{id: 1, price: 10, on_hold: false}]);
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+        }
+
+        #[ink_lang::test]
+        fn custodial_listing_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(erc721.publish_for_sale_custodial(1, 100), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(contract));
+            assert_eq!(erc721.seller_of(1), Some(accounts.alice));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 1, price: 100, on_hold: false}]);
+            // seller cannot move the token out from under a buyer
+            assert_eq!(erc721.transfer(accounts.eve, 1), Err(Error::NotApproved));
+            assert_eq!(erc721.update_price(1, 120), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(120);
+            assert_eq!(erc721.buy_nft(1), Ok(120));
+
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            assert_eq!(get_balance(accounts.alice), 1_000_120);
+            assert_eq!(erc721.seller_of(1), None);
+            assert_eq!(erc721.get_tokens_for_sale(), vec![]);
+        }
+
+        #[ink_lang::test]
+        fn custodial_listing_removal_returns_token() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.publish_for_sale_custodial(1, 100), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.remove_from_sale(1), Err(Error::NotApproved));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.remove_from_sale(1), Ok(()));
+
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![]);
+            assert_eq!(erc721.transfer(accounts.eve, 1), Ok(()));
+        }
+
+        #[ink_lang::test]
+        fn best_offer_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.best_offer(1), None);
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.make_offer(1, 100), Ok(()));
+            set_caller(accounts.charlie);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(20);
+            assert_eq!(erc721.make_offer(1, 100), Ok(()));
+            set_caller(accounts.django);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(5);
+            assert_eq!(erc721.make_offer(1, 100), Ok(()));
+            // the higher offer is reported
+            assert_eq!(erc721.best_offer(1), Some((accounts.charlie, 20)));
+
+            // cancelling the top offer demotes to the next and refunds it
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.cancel_offer(1), Ok(()));
+            assert_eq!(get_balance(accounts.charlie), 1_020);
+            assert_eq!(erc721.best_offer(1), Some((accounts.bob, 10)));
+            assert_eq!(erc721.cancel_offer(1), Err(Error::OfferNotFound));
+
+            // accepting the best offer
+            set_caller(accounts.alice);
+            let (offeror, _) = erc721.best_offer(1).unwrap();
+            assert_eq!(erc721.accept_offer(1, offeror), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            assert_eq!(get_balance(accounts.alice), 1_000_010);
+            assert_eq!(erc721.best_offer(1), Some((accounts.django, 5)));
+        }
+
+        #[ink_lang::test]
+        fn expiring_offer_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.make_offer(1, 5), Ok(()));
+            assert_eq!(erc721.make_offer(2, 5), Ok(()));
+            assert_eq!(erc721.offer_of(1, accounts.bob), Some((10, 5)));
+
+            // before expiry
+            advance_blocks(5);
+            set_caller(accounts.alice);
+            assert_eq!(erc721.accept_offer(1, accounts.bob), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+
+            // after expiry
+            advance_blocks(1);
+            assert_eq!(erc721.accept_offer(2, accounts.bob), Err(Error::ListingExpired));
+            assert_eq!(erc721.owner_of(2), Some(accounts.alice));
+
+            // anyone reclaims the escrow to the maker
+            set_caller(accounts.eve);
+            assert_eq!(erc721.reclaim_expired_offer(2, accounts.bob), Ok(()));
+            assert_eq!(get_balance(accounts.bob), 1_010);
+            assert_eq!(erc721.offer_of(2, accounts.bob), None);
+            assert_eq!(erc721.best_offer(2), None);
+        }
+
+        #[ink_lang::test]
+        fn auction_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.create_auction(1, 50, 5, None, 10), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(contract));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(60);
+            assert_eq!(erc721.bid(1), Ok(()));
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.bid(1), Err(Error::BidTooLow));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(70);
+            assert_eq!(erc721.bid(1), Ok(()));
+            // outbid bidder is refunded
+            assert_eq!(get_balance(accounts.bob), 1_060);
+
+            assert_eq!(erc721.settle_auction(1), Err(Error::AuctionNotEnded));
+            advance_blocks(10);
+            assert_eq!(erc721.bid(1), Err(Error::AuctionEnded));
+            assert_eq!(erc721.settle_auction(1), Ok(()));
+
+            assert_eq!(erc721.owner_of(1), Some(accounts.charlie));
+            assert_eq!(get_balance(accounts.alice), 1_000_070);
+            assert_eq!(erc721.get_auction(1), None);
+        }
+
+        #[ink_lang::test]
+        fn cancel_auction_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.create_auction(1, 50, 5, None, 10), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.cancel_auction(1), Err(Error::NotApproved));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.cancel_auction(1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+            assert_eq!(erc721.get_auction(1), None);
+        }
+
+        #[ink_lang::test]
+        fn cancel_auction_with_bid_fails() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.create_auction(1, 50, 5, None, 10), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(60);
+            assert_eq!(erc721.bid(1), Ok(()));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.cancel_auction(1), Err(Error::NotAllowed));
+            assert_eq!(erc721.owner_of(1), Some(contract));
+        }
+
+        #[ink_lang::test]
+        fn extend_auction_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.create_auction(1, 50, 5, None, 10), Ok(()));
+            assert_eq!(erc721.extend_auction(1, 5), Ok(()));
+
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.extend_auction(1, 5), Err(Error::NotApproved));
+            // collection owner can extend too
+            set_caller(accounts.alice);
+            assert_eq!(erc721.extend_auction(1, 5), Ok(()));
+
+            advance_blocks(19);
+            assert_eq!(erc721.settle_auction(1), Err(Error::AuctionNotEnded));
+            advance_blocks(1);
+            assert_eq!(erc721.extend_auction(1, 5), Err(Error::AuctionEnded));
+            assert_eq!(erc721.settle_auction(1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+        }
+
+        #[ink_lang::test]
+        fn auction_below_reserve_fails() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.create_auction(1, 50, 5, None, 10), Ok(()));
+
+            // opening bid must meet the reserve
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(40);
+            assert_eq!(erc721.bid(1), Err(Error::BidTooLow));
+
+            advance_blocks(10);
+            let events_before = ink_env::test::recorded_events().count();
+            assert_eq!(erc721.settle_auction(1), Ok(()));
+
+            // token returns to the seller
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+            assert_eq!(get_balance(accounts.bob), 1_000);
+            let events: Vec<_> = ink_env::test::recorded_events().skip(events_before).collect();
+            assert_eq!(events.len(), 2);
+            match <Event as scale::Decode>::decode(&mut &events[1].data[..]) {
+                Ok(Event::AuctionFailed(event)) => {
+                    assert_eq!(event.id, 1);
+                    assert_eq!(event.highest_bid, None);
+                }
+                _ => panic!("expected AuctionFailed event"),
+            }
+        }
+
+        #[ink_lang::test]
+        fn auction_min_increment_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.create_auction(1, 50, 10, None, 10), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(50);
+            assert_eq!(erc721.bid(1), Ok(()));
+
+            // one short of the increment
+            set_caller(accounts.charlie);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(59);
+            assert_eq!(erc721.bid(1), Err(Error::BidTooLow));
+            // exactly the increment
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(60);
+            assert_eq!(erc721.bid(1), Ok(()));
+            assert_eq!(erc721.get_auction(1).unwrap().highest_bid, Some((accounts.charlie, 60)));
+        }
+
+        #[ink_lang::test]
+        fn auction_buy_now_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.create_auction(1, 50, 5, Some(100), 10), Ok(()));
+            assert_eq!(erc721.create_auction(2, 50, 5, Some(100), 10), Ok(()));
+            assert_eq!(erc721.create_auction(3, 50, 5, None, 10), Err(Error::TokenNotFound));
+
+            // without a standing bid
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(99);
+            assert_eq!(erc721.buy_now(1), Err(Error::NotEnoughSent));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            assert_eq!(erc721.buy_now(1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            assert_eq!(erc721.get_auction(1), None);
+            assert_eq!(get_balance(accounts.alice), 1_000_100);
+
+            // with a standing bid, which is refunded
+            set_caller(accounts.charlie);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(60);
+            assert_eq!(erc721.bid(2), Ok(()));
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(120);
+            assert_eq!(erc721.buy_now(2), Ok(()));
+            assert_eq!(erc721.owner_of(2), Some(accounts.bob));
+            assert_eq!(get_balance(accounts.charlie), 1_060);
+            // overpayment is returned
+            assert_eq!(get_balance(accounts.bob), 1_020);
+            assert_eq!(get_balance(accounts.alice), 1_000_200);
+        }
+
+        #[ink_lang::test]
+        fn marketplace_fees_work() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_fee_bps(MAX_BPS + 1), Err(Error::NotAllowed));
+            assert_eq!(erc721.set_fee_bps(250), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 1_000), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 2_000), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(1_000);
+            assert_eq!(erc721.buy_nft(1), Ok(1_000));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(2_000);
+            assert_eq!(erc721.buy_nft(2), Ok(2_000));
+            // seller receives price without fee
+            assert_eq!(get_balance(accounts.alice), 1_000_000 + 975 + 1_950);
+            assert_eq!(erc721.accrued_fees(), 75);
+
+            assert_eq!(erc721.withdraw_fees(accounts.bob), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.withdraw_fees(accounts.frank), Ok(75));
+            assert_eq!(get_balance(accounts.frank), 75);
+            assert_eq!(erc721.accrued_fees(), 0);
+        }
+
+        #[ink_lang::test]
+        fn wrap_and_unwrap_work() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let external = AccountId::from([0xee; 32]);
+            ExternalErc721Mock::mint(external, 7, accounts.bob);
+            let mut erc721 = Erc721::new();
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.wrap(external, 8), Err(Error::CannotTransferToken));
+            let id = erc721.wrap(external, 7).unwrap();
+            assert_eq!(ExternalErc721Mock::owner_of(external, 7), Some(contract));
+            assert_eq!(erc721.owner_of(id), Some(accounts.bob));
+            assert_eq!(erc721.wrapped_of(id), Some((external, 7)));
+
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.unwrap(id), Err(Error::NotOwner));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.unwrap(id), Ok(()));
+            assert_eq!(ExternalErc721Mock::owner_of(external, 7), Some(accounts.bob));
+            assert_eq!(erc721.owner_of(id), None);
+            assert_eq!(erc721.wrapped_of(id), None);
+            assert_eq!(erc721.unwrap(id), Err(Error::TokenNotFound));
+        }
+
+        #[ink_lang::test]
+        fn wrapped_token_cannot_be_burned() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let external = AccountId::from([0xee; 32]);
+            ExternalErc721Mock::mint(external, 7, accounts.bob);
+            let mut erc721 = Erc721::new();
+
+            set_caller(accounts.bob);
+            let id = erc721.wrap(external, 7).unwrap();
+            assert_eq!(erc721.burn(id), Err(Error::NotAllowed));
+            assert_eq!(erc721.burn_range(id, 1), Ok(0));
+            assert_eq!(erc721.burn_and_mint(vec![id], NftData { poebat: None }), Err(Error::NotAllowed));
+            assert_eq!(erc721.owner_of(id), Some(accounts.bob));
+
+            // nobody can mint the id again and take the external token
+            set_caller(accounts.alice);
+            assert_eq!(erc721.mint(id, NftData { poebat: None }), Err(Error::TokenExists));
+            assert_eq!(erc721.unwrap(id), Err(Error::NotOwner));
+            assert_eq!(ExternalErc721Mock::owner_of(external, 7), Some(contract));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.unwrap(id), Ok(()));
+            assert_eq!(ExternalErc721Mock::owner_of(external, 7), Some(accounts.bob));
+            assert_eq!(erc721.wrapped_of(id), None);
+        }
+
+        #[ink_lang::test]
+        fn burn_fee_does_not_block_unwrap_and_burn_and_mint() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let external = AccountId::from([0xee; 32]);
+            ExternalErc721Mock::mint(external, 7, accounts.alice);
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_burn_fee(10), Ok(()));
+
+            let id = erc721.wrap(external, 7).unwrap();
+            assert_eq!(erc721.unwrap(id), Ok(()));
+            assert_eq!(ExternalErc721Mock::owner_of(external, 7), Some(accounts.alice));
+
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            let id = erc721.burn_and_mint(vec![1, 2], NftData { poebat: None }).unwrap();
+            assert_eq!(erc721.tokens_of_owner(accounts.alice), vec![id]);
+        }
+
+        #[ink_lang::test]
+        fn lookup_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.lookup(1), (true, Some(accounts.alice)));
+            assert_eq!(erc721.lookup(2), (false, None));
+
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.lookup(1), (false, None));
+        }
+
+        #[ink_lang::test]
+        fn owners_of_batch_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.transfer_from(accounts.alice, accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+            assert_eq!(
+                erc721.owners_of_batch(vec![1, 2, 3]),
+                vec![(1, Some(accounts.bob)), (2, None), (3, Some(accounts.alice))]
+            );
+            assert_eq!(erc721.owners_of_batch((0..200).collect()).len(), MAX_BATCH_SIZE);
+        }
+
+        #[ink_lang::test]
+        fn count_for_sale_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.transfer_from(accounts.alice, accounts.bob, 3), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            assert_eq!(erc721.publish_for_sale_custodial(2, 20), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.publish_for_sale_custodial(3, 30), Ok(()));
+            assert_eq!(erc721.count_for_sale(), 3);
+            assert_eq!(erc721.count_for_sale_by_owner(accounts.alice), 2);
+            assert_eq!(erc721.count_for_sale_by_owner(accounts.bob), 1);
+
+            assert_eq!(erc721.remove_from_sale(3), Ok(()));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+            assert_eq!(erc721.count_for_sale(), 1);
+            assert_eq!(erc721.count_for_sale_by_owner(accounts.alice), 1);
+            assert_eq!(erc721.count_for_sale_by_owner(accounts.bob), 0);
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.remove_from_sale(2), Ok(()));
+            assert_eq!(erc721.count_for_sale(), 0);
+            assert_eq!(erc721.count_for_sale_by_owner(accounts.alice), 0);
+        }
+
+        #[ink_lang::test]
+        fn sweep_stale_listings_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 100), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 200), Ok(()));
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            let events_before = ink_env::test::recorded_events().count();
+
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.sweep_stale_listings(vec![1, 2, 3]), Ok(1));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale { id: 2, price: 200, on_hold: false }]);
+            assert_eq!(erc721.seller_of(1), None);
+            assert_eq!(erc721.sweep_stale_listings(vec![1]), Ok(0));
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(emitted_events.len(), events_before + 1);
+            let decoded = <Event as scale::Decode>::decode(&mut &emitted_events[events_before].data[..])
+                .expect("invalid event");
+            assert!(matches!(decoded, Event::Unlisted(Unlisted { seller, id: 1, .. }) if seller == accounts.alice));
+        }
+
+        #[ink_lang::test]
+        fn buy_nft_rejects_seller_and_owner() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 100), Ok(()));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            // the caller is both the recorded seller and the owner
+            assert_eq!(erc721.buy_nft(1), Err(Error::NotApproved));
+
+            // the recorded seller no longer owns the token
+            assert_eq!(erc721.transfer_from(accounts.alice, accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.seller_of(1), Some(accounts.alice));
+            assert_eq!(erc721.buy_nft(1), Err(Error::NotApproved));
+
+            // the owner is not the recorded seller
+            set_caller(accounts.bob);
+            assert_eq!(erc721.buy_nft(1), Err(Error::NotApproved));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            assert_eq!(erc721.seller_of(1), Some(accounts.alice));
+        }
+
+        #[ink_lang::test]
+        fn buy_nft_stale_listing_fails() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 100), Ok(()));
+            // the owner changes after listing
+            assert_eq!(erc721.transfer_from(accounts.alice, accounts.bob, 1), Ok(()));
+
+            set_caller(accounts.charlie);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            assert_eq!(erc721.buy_nft(1), Err(Error::NotForSale));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            assert_eq!(get_balance(accounts.alice), 1_000_000);
+            assert_eq!(get_balance(accounts.bob), 1_000);
+
+            // the seller revokes the marketplace approval after listing
+            set_caller(accounts.alice);
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 100), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, false), Ok(()));
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.buy_nft(2), Err(Error::NotForSale));
+        }
+
+        #[ink_lang::test]
+        fn mint_to_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(
+                erc721.mint_to(AccountId::from([0x0; 32]), 1, NftData { poebat: None }),
+                Err(Error::NotAllowed)
+            );
+            assert_eq!(erc721.mint_to(accounts.bob, 1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.balance_of(accounts.bob), 1);
+            assert_eq!(erc721.balance_of(accounts.alice), 0);
+            assert_eq!(erc721.creator_of(1), Some(accounts.alice));
+            assert_eq!(erc721.mint_to(accounts.bob, 1, NftData { poebat: None }), Err(Error::TokenExists));
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let event = <Event as scale::Decode>::decode(&mut &emitted_events[0].data[..]).unwrap();
+            assert!(matches!(
+                event,
+                Event::Transfer(Transfer { from: Some(from), to: Some(to), id: 1 })
+                    if from == AccountId::from([0x0; 32]) && to == accounts.bob
+            ));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.mint_to(accounts.bob, 2, NftData { poebat: None }), Err(Error::NotAllowed));
+        }
+
+        #[ink_lang::test]
+        fn default_royalty_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_default_royalty(Some((accounts.charlie, MAX_BPS + 1))), Err(Error::InvalidRoyalty));
+            assert_eq!(erc721.set_default_royalty(Some((accounts.charlie, 500))), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint_with_royalty(2, NftData { poebat: None }, accounts.django, 1_000), Ok(()));
+
+            assert_eq!(erc721.royalty_recipients(1), vec![(accounts.charlie, 500)]);
+            assert_eq!(erc721.royalty_info(1, 1_000), 50);
+            assert_eq!(erc721.royalty_recipients(2), vec![(accounts.django, 1_000)]);
+            assert_eq!(erc721.royalty_info(2, 1_000), 100);
+
+            assert_eq!(erc721.set_default_royalty(None), Ok(()));
+            assert_eq!(erc721.royalty_info(1, 1_000), 0);
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_default_royalty(Some((accounts.bob, 500))), Err(Error::NotOwner));
+        }
+
+        #[ink_lang::test]
+        fn royalty_fallback_order() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_royalty(1, vec![(accounts.django, 1_000)]), Ok(()));
+
+            // neither own nor default royalty
+            assert_eq!(erc721.royalty_info(2, 1_000), 0);
+            assert_eq!(erc721.royalty_recipients(2), vec![]);
+
+            // own royalty wins over the default
+            assert_eq!(erc721.set_default_royalty(Some((accounts.charlie, 500))), Ok(()));
+            assert_eq!(erc721.royalty_info(1, 1_000), 100);
+            assert_eq!(erc721.royalty_recipients(1), vec![(accounts.django, 1_000)]);
+
+            // token without own royalty falls through to the default
+            assert_eq!(erc721.royalty_info(2, 1_000), 50);
+            assert_eq!(erc721.royalty_recipients(2), vec![(accounts.charlie, 500)]);
+        }
+
+        #[ink_lang::test]
+        fn batch_transfer_from_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            for id in 1..=3 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            assert_eq!(erc721.set_approval_for_all(accounts.bob, true), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.batch_transfer_from(accounts.alice, accounts.charlie, vec![1, 2, 3]), Ok(()));
+            assert_eq!(erc721.balance_of(accounts.alice), 0);
+            assert_eq!(erc721.balance_of(accounts.charlie), 3);
+        }
+
+        #[ink_lang::test]
+        fn batch_transfer_event_carries_all_ids() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new_configured(Config {
+                batch_transfer_events: true,
+                ..Default::default()
+            });
+            assert_eq!(erc721.reserve_mint(accounts.alice, 3), Ok(vec![1, 2, 3]));
+            assert_eq!(erc721.batch_transfer_from(accounts.alice, accounts.bob, vec![1, 3]), Ok(()));
+            assert_eq!(erc721.tokens_of_owner(accounts.bob), vec![1, 3]);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let decoded = emitted_events
+                .iter()
+                .map(|event| <Event as scale::Decode>::decode(&mut &event.data[..]).expect("invalid event"))
+                .collect::<Vec<_>>();
+            assert!(!decoded.iter().any(|event| matches!(event, Event::Transfer(_))));
+            let batches = decoded
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::BatchTransfer(batch) => Some(batch),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(batches.len(), 2);
+            assert_eq!(batches[0].from, Some(AccountId::from([0x0; 32])));
+            assert_eq!(batches[0].ids, vec![1, 2, 3]);
+            assert_eq!(batches[1].from, Some(accounts.alice));
+            assert_eq!(batches[1].to, Some(accounts.bob));
+            assert_eq!(batches[1].ids, vec![1, 3]);
+        }
+
+        #[ink_lang::test]
+        fn batch_transfer_from_without_authority_fails() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            for id in 1..=3 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            assert_eq!(erc721.approve(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.approve(accounts.bob, 3), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(
+                erc721.batch_transfer_from(accounts.alice, accounts.charlie, vec![1, 2, 3]),
+                Err(Error::NotApproved)
+            );
+            assert_eq!(
+                erc721.batch_transfer_from(accounts.alice, accounts.charlie, vec![1, 1]),
+                Err(Error::NotAllowed)
+            );
+            assert_eq!(erc721.balance_of(accounts.alice), 3);
+            assert_eq!(erc721.balance_of(accounts.charlie), 0);
+        }
+
+        #[ink_lang::test]
+        fn custody_works() {
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert!(!erc721.is_in_custody(1));
+            assert_eq!(erc721.custodied_tokens(), vec![]);
+
+            assert_eq!(erc721.publish_for_sale_custodial(1, 10), Ok(()));
+            assert!(erc721.is_in_custody(1));
+            assert!(!erc721.is_in_custody(2));
+            assert_eq!(erc721.custodied_tokens(), vec![1]);
+
+            assert_eq!(erc721.remove_from_sale(1), Ok(()));
+            assert!(!erc721.is_in_custody(1));
+            assert_eq!(erc721.custodied_tokens(), vec![]);
+        }
+
+        #[ink_lang::test]
+        fn mint_fee_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.fee_recipient(), accounts.alice);
+            assert_eq!(erc721.set_mint_fee(100), Ok(()));
+            assert_eq!(erc721.set_fee_recipient(accounts.django), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(99);
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Err(Error::NotEnoughSent));
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Err(Error::NotEnoughSent));
+            assert_eq!(erc721.total_supply(), 0);
+
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(get_balance(accounts.django), 100);
+            assert_eq!(get_balance(accounts.bob), 1_000);
+
+            // excess is refunded
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(150);
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Ok(2));
+            assert_eq!(get_balance(accounts.django), 200);
+            assert_eq!(get_balance(accounts.bob), 1_050);
+        }
+
+        #[ink_lang::test]
+        fn revenue_shares_split_mint_fee() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_mint_fee(1_000), Ok(()));
+            assert_eq!(
+                erc721.set_revenue_shares(vec![(accounts.django, 7_000), (accounts.eve, 2_000)]),
+                Err(Error::InvalidShares)
+            );
+            assert_eq!(erc721.set_revenue_shares(vec![(accounts.django, 7_000), (accounts.eve, 3_000)]), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(1_000);
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.pending_withdrawal_of(accounts.django), 700);
+            assert_eq!(erc721.pending_withdrawal_of(accounts.eve), 300);
+            assert_eq!(erc721.pending_withdrawal_of(accounts.alice), 0);
+
+            set_caller(accounts.django);
+            assert_eq!(erc721.withdraw(), Ok(700));
+            assert_eq!(get_balance(accounts.django), 700);
+            assert_eq!(erc721.pending_withdrawal_of(accounts.django), 0);
+            assert_eq!(erc721.withdraw(), Err(Error::NotAllowed));
+        }
+
+        #[ink_lang::test]
+        fn burn_range_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            for id in 1..=5 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            assert_eq!(erc721.transfer(accounts.bob, 2), Ok(()));
+            assert_eq!(erc721.transfer(accounts.bob, 4), Ok(()));
+
+            // 6 and 7 do not exist, 2 and 4 belong to bob
+            assert_eq!(erc721.burn_range(1, 7), Ok(3));
+            assert_eq!(erc721.tokens_of_owner(accounts.alice), vec![]);
+            assert_eq!(erc721.tokens_of_owner(accounts.bob), vec![2, 4]);
+            assert_eq!(erc721.total_supply(), 2);
+            assert_eq!(erc721.burn_range(1, 7), Ok(0));
+            assert_eq!(erc721.burn_range(1, MAX_BATCH_SIZE as u32 + 1), Err(Error::NotAllowed));
+        }
+
+        #[ink_lang::test]
+        fn free_mint_refunds_sent_value() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint_fee(), 0);
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(250);
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Ok(2));
+            // sent value is not debited off-chain, so both refunds show up as gains
+            assert_eq!(get_balance(accounts.bob), 1_500);
+            assert_eq!(get_balance(contract), 1_000_000 - 500);
+        }
+
+        #[ink_lang::test]
+        fn transfer_fee_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_transfer_fee(50), Ok(()));
+            assert_eq!(erc721.set_fee_recipient(accounts.django), Ok(()));
+
+            // minting is exempt
+            assert_eq!(erc721.mint_to(accounts.bob, 1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(get_balance(accounts.django), 0);
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(49);
+            assert_eq!(erc721.transfer(accounts.charlie, 1), Err(Error::NotEnoughSent));
+            assert_eq!(erc721.transfer_from(accounts.bob, accounts.charlie, 1), Err(Error::NotEnoughSent));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(50);
+            assert_eq!(erc721.transfer(accounts.charlie, 1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.charlie));
+            assert_eq!(get_balance(accounts.django), 50);
+            assert_eq!(erc721.set_transfer_fee(0), Err(Error::NotOwner));
+        }
+
+        #[ink_lang::test]
+        fn burn_fee_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.burn_fee(), 0);
+            for id in 1..=3 {
+                assert_eq!(erc721.mint_to(accounts.bob, id, NftData { poebat: None }), Ok(()));
+            }
+            assert_eq!(erc721.set_fee_recipient(accounts.django), Ok(()));
+
+            // free burn
+            set_caller(accounts.bob);
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(get_balance(accounts.django), 0);
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.set_burn_fee(100), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_burn_fee(0), Err(Error::NotOwner));
+
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(99);
+            assert_eq!(erc721.burn(2), Err(Error::NotEnoughSent));
+            assert_eq!(erc721.owner_of(2), Some(accounts.bob));
+
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            assert_eq!(erc721.burn(2), Ok(()));
+            assert_eq!(erc721.owner_of(2), None);
+            assert_eq!(get_balance(accounts.django), 100);
+            assert_eq!(get_balance(accounts.bob), 1_000);
+        }
+
+        #[ink_lang::test]
+        fn supports_interface_works() {
+            let erc721 = Erc721::new();
+            assert!(erc721.supports_interface(INTERFACE_ERC721));
+            assert!(erc721.supports_interface(INTERFACE_METADATA));
+            assert!(erc721.supports_interface(INTERFACE_ENUMERABLE));
+            assert!(!erc721.supports_interface([0xde, 0xad, 0xbe, 0xef]));
+        }
+
+        #[ink_lang::test]
+        fn burn_and_mint_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Ok(1));
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Ok(2));
+            assert_eq!(erc721.mint_to(accounts.bob, 3, NftData { poebat: None }), Ok(()));
+
+            // one token of another account burns nothing
+            assert_eq!(erc721.burn_and_mint(vec![1, 3], NftData { poebat: None }), Err(Error::NotOwner));
+            assert_eq!(erc721.balance_of(accounts.alice), 2);
+
+            let forged = NftData { poebat: Some(String::from("sword+1")) };
+            assert_eq!(erc721.burn_and_mint(vec![1, 2], NftData { poebat: Some(String::from("sword+1")) }), Ok(4));
+            assert_eq!(erc721.balance_of(accounts.alice), 1);
+            assert_eq!(erc721.owner_of(1), None);
+            assert_eq!(erc721.owner_of(2), None);
+            assert_eq!(erc721.owner_of(4), Some(accounts.alice));
+            assert_eq!(erc721.get_nft_info(4), Ok(forged));
+        }
+
+        #[ink_lang::test]
+        fn payment_without_balance_fails() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_fee_bps(MAX_BPS), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 100), Ok(()));
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            assert_eq!(erc721.buy_nft(1), Ok(100));
+
+            // the contract lost the fees it kept, a fee withdrawal is never credited
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 10);
+            set_caller(accounts.alice);
+            let before = ink_env::test::recorded_events().count();
+            assert_eq!(erc721.withdraw_fees(accounts.django), Err(Error::InsufficientBalance));
+            assert_eq!(erc721.accrued_fees(), 100);
+            assert_eq!(erc721.pending_withdrawal_of(accounts.django), 0);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            assert_eq!(emitted_events.len(), before + 1);
+            let event = <Event as scale::Decode>::decode(&mut &emitted_events[before].data[..]).unwrap();
+            assert!(matches!(
+                event,
+                Event::TransferFailed(TransferFailed { to, amount: 100 }) if to == accounts.django
+            ));
+        }
+
+        #[ink_lang::test]
+        fn rejected_payment_is_credited() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            // the off-chain environment never rejects a transfer the contract can afford,
+            // so the fallback `pay_or_credit` takes on rejection is exercised directly
+            assert_eq!(erc721.credit_failed_payment(accounts.django, 100), Ok(()));
+            assert_eq!(erc721.pending_withdrawal_of(accounts.django), 100);
+
+            set_caller(accounts.django);
+            assert_eq!(erc721.withdraw(), Ok(100));
+            assert_eq!(get_balance(accounts.django), 100);
+            assert_eq!(erc721.pending_withdrawal_of(accounts.django), 0);
+        }
+
+        #[ink_lang::test]
+        fn preview_buy_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_fee_bps(250), Ok(()));
+            assert_eq!(erc721.mint_with_royalty(1, NftData { poebat: None }, accounts.charlie, 1_000), Ok(()));
+            assert_eq!(erc721.preview_buy(1), Err(Error::NotForSale));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 1_000), Ok(()));
+
+            let breakdown = erc721.preview_buy(1).unwrap();
+            assert_eq!(
+                breakdown,
+                BuyBreakdown { price: 1_000, marketplace_fee: 25, royalty: 100, seller_proceeds: 875 }
+            );
+            assert_eq!(breakdown.marketplace_fee + breakdown.royalty + breakdown.seller_proceeds, breakdown.price);
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(1_000);
+            assert_eq!(erc721.buy_nft(1), Ok(1_000));
+            assert_eq!(get_balance(accounts.alice), 1_000_000 + breakdown.seller_proceeds);
+            assert_eq!(get_balance(accounts.charlie), 1_000 + breakdown.royalty);
+            assert_eq!(erc721.accrued_fees(), breakdown.marketplace_fee);
+        }
+
+        #[ink_lang::test]
+        fn new_configured_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new_configured(Config {
+                name: String::from("Poebat"),
+                symbol: String::from("PBT"),
+                fee_bps: 250,
+                fee_recipient: Some(accounts.django),
+                max_supply: Some(10),
+                base_uri: Some(String::from("https://poebat.io/")),
+                max_royalty_bps: Some(500),
+                batch_transfer_events: false,
+                allow_free_listings: false,
+            });
+            assert_eq!(erc721.name(), String::from("Poebat"));
+            assert_eq!(erc721.symbol(), String::from("PBT"));
+            assert_eq!(erc721.fee_bps(), 250);
+            assert_eq!(erc721.fee_recipient(), accounts.django);
+            assert_eq!(erc721.max_supply(), Some(10));
+            assert_eq!(erc721.base_uri(), Some(String::from("https://poebat.io/")));
+            assert_eq!(erc721.max_royalty_bps(), 500);
+            assert_eq!(erc721.owner(), accounts.alice);
+
+            assert_eq!(erc721.mint(7, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.token_uri(7), Some(String::from("https://poebat.io/7")));
+        }
+
+        #[ink_lang::test]
+        fn set_name_and_symbol_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_name(String::from("Poebat")), Ok(()));
+            assert_eq!(erc721.set_symbol(String::from("PBT")), Ok(()));
+            assert_eq!(erc721.name(), String::from("Poebat"));
+            assert_eq!(erc721.symbol(), String::from("PBT"));
+
+            let event = ink_env::test::recorded_events().last().unwrap();
+            let decoded = <Event as scale::Decode>::decode(&mut &event.data[..]).unwrap();
+            assert!(matches!(
+                decoded,
+                Event::CollectionMetadataUpdated(CollectionMetadataUpdated { name, symbol })
+                    if name == "Poebat" && symbol == "PBT"
+            ));
+
+            assert_eq!(erc721.set_name("a".repeat(MAX_NAME_LEN + 1)), Err(Error::NameTooLong));
+            assert_eq!(erc721.set_symbol("a".repeat(MAX_SYMBOL_LEN + 1)), Err(Error::SymbolTooLong));
+            assert_eq!(erc721.name(), String::from("Poebat"));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_name(String::from("Rebrand")), Err(Error::NotOwner));
+            assert_eq!(erc721.set_symbol(String::from("RBR")), Err(Error::NotOwner));
+            assert_eq!(erc721.symbol(), String::from("PBT"));
+        }
+
+        #[ink_lang::test]
+        fn renounce_minter_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.add_minter(accounts.bob), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.mint_to(accounts.charlie, 1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.renounce_minter(), Ok(()));
+            assert!(!erc721.is_minter(accounts.bob));
+            assert_eq!(erc721.mint_to(accounts.charlie, 2, NftData { poebat: None }), Err(Error::NotAllowed));
+            assert_eq!(erc721.renounce_minter(), Err(Error::NotAllowed));
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let event = <Event as scale::Decode>::decode(&mut &emitted_events.last().unwrap().data[..]).unwrap();
+            assert!(matches!(event, Event::MinterRemoved(MinterRemoved { account }) if account == accounts.bob));
+        }
+
+        #[ink_lang::test]
+        fn lock_supply_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.lock_supply(), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.lock_supply(), Ok(()));
+            assert!(erc721.supply_locked());
+
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Err(Error::SupplyLocked));
+            assert_eq!(erc721.mint_next(NftData { poebat: None }), Err(Error::SupplyLocked));
+            assert_eq!(erc721.mint_to(accounts.bob, 3, NftData { poebat: None }), Err(Error::SupplyLocked));
+            assert_eq!(erc721.reserve_mint(accounts.bob, 2), Err(Error::SupplyLocked));
+            assert_eq!(
+                erc721.mint_with_cid(3, [&[0x12, 0x20][..], &[0xab; 32][..]].concat()),
+                Err(Error::SupplyLocked)
+            );
+            assert_eq!(
+                erc721.mint_with_royalty(3, NftData { poebat: None }, accounts.bob, 100),
+                Err(Error::SupplyLocked)
+            );
+            assert_eq!(erc721.burn_and_mint(vec![1, 2], NftData { poebat: None }), Err(Error::SupplyLocked));
+            assert_eq!(erc721.total_supply(), 2);
+        }
+
+        #[ink_lang::test]
+        fn tokens_with_attribute_works() {
+            let mut erc721 = Erc721::new();
+            let rarity = || String::from("rarity");
+            for (id, value) in [(1, "legendary"), (2, "common"), (3, "legendary"), (4, "legendary")] {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+                assert_eq!(erc721.set_attribute(id, rarity(), String::from(value)), Ok(()));
+            }
+            assert_eq!(erc721.tokens_with_attribute(rarity(), String::from("legendary")), vec![1, 3, 4]);
+            assert_eq!(erc721.tokens_with_attribute(rarity(), String::from("common")), vec![2]);
+            assert_eq!(erc721.tokens_with_attribute_paged(rarity(), String::from("legendary"), 1, 1), vec![3]);
+
+            // changing and burning update the index
+            assert_eq!(erc721.set_attribute(3, rarity(), String::from("common")), Ok(()));
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.tokens_with_attribute(rarity(), String::from("legendary")), vec![4]);
+            assert_eq!(erc721.tokens_with_attribute(rarity(), String::from("common")), vec![2, 3]);
+            assert_eq!(erc721.get_attribute(1, rarity()), None);
+        }
+
+        #[ink_lang::test]
+        fn transfer_ownership_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.transfer_ownership(accounts.bob), Ok(()));
+            assert_eq!(erc721.pending_owner(), Some(accounts.bob));
+            assert_eq!(erc721.owner(), accounts.alice);
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.transfer_ownership(accounts.bob), Err(Error::NotOwner));
+            assert_eq!(erc721.accept_ownership(), Ok(()));
+            assert_eq!(erc721.owner(), accounts.bob);
+            assert_eq!(erc721.pending_owner(), None);
+        }
+
+        #[ink_lang::test]
+        fn accept_ownership_by_wrong_account_fails() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.transfer_ownership(accounts.bob), Ok(()));
+
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.accept_ownership(), Err(Error::NotAllowed));
+            assert_eq!(erc721.owner(), accounts.alice);
+        }
+
+        #[ink_lang::test]
+        fn cancel_ownership_transfer_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.transfer_ownership(accounts.bob), Ok(()));
+            assert_eq!(erc721.cancel_ownership_transfer(), Ok(()));
+            assert_eq!(erc721.pending_owner(), None);
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.accept_ownership(), Err(Error::NotAllowed));
+            assert_eq!(erc721.owner(), accounts.alice);
+        }
+
+        #[ink_lang::test]
+        fn stake_lock_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(accounts.django, true), Ok(()));
+
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.stake_lock(1, accounts.alice), Err(Error::NotApproved));
+            set_caller(accounts.django);
+            assert_eq!(erc721.stake_lock(1, accounts.alice), Ok(()));
+            assert_eq!(erc721.staker_of(1), Some(accounts.alice));
+            assert_eq!(erc721.stake_lock(1, accounts.alice), Err(Error::TokenStaked));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.transfer(accounts.bob, 1), Err(Error::TokenStaked));
+            assert_eq!(erc721.burn(1), Err(Error::TokenStaked));
+            assert_eq!(erc721.stake_unlock(1), Err(Error::NotApproved));
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+
+            set_caller(accounts.django);
+            assert_eq!(erc721.stake_unlock(1), Ok(()));
+            assert_eq!(erc721.staker_of(1), None);
+            set_caller(accounts.alice);
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+        }
+
+        #[ink_lang::test]
+        fn burn_removes_listing_and_approval() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.approve(contract, 1), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            assert_eq!(erc721.price_of(1), Ok(10));
+
+            assert_eq!(erc721.burn(1), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![]);
+            assert_eq!(erc721.price_of(1), Err(Error::NotForSale));
+            assert_eq!(erc721.seller_of(1), None);
+            assert_eq!(erc721.count_for_sale_by_owner(accounts.alice), 0);
+            assert_eq!(erc721.get_approved(1), None);
+        }
+
+        #[ink_lang::test]
+        fn burn_removes_royalty() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_royalty(1, vec![(accounts.charlie, 500)]), Ok(()));
+            assert_eq!(erc721.burn(1), Ok(()));
+
+            // the id minted again by another account does not inherit the old royalty
+            set_caller(accounts.bob);
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.royalty_recipients(1), vec![]);
+            assert_eq!(erc721.royalty_info(1, 10_000), 0);
+        }
+
+        #[ink_lang::test]
+        fn burn_removes_cid_and_metadata_lock() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint_with_cid(1, [&[0x12, 0x20][..], &[0xab; 32][..]].concat()), Ok(()));
+            assert_eq!(erc721.freeze_metadata(1), Ok(()));
+            assert_eq!(erc721.burn(1), Ok(()));
+
+            // the id minted again starts with its own metadata, unlocked
+            set_caller(accounts.bob);
+            assert_eq!(erc721.mint(1, NftData { poebat: Some(String::from("uri")) }), Ok(()));
+            assert_eq!(erc721.token_uri(1), Some(String::from("uri")));
+            assert!(!erc721.is_metadata_frozen(1));
+            assert_eq!(erc721.set_token_uri(1, String::from("new")), Ok(()));
+        }
+
+        #[ink_lang::test]
+        fn token_info_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.token_info(1), Err(Error::TokenNotFound));
+            assert_eq!(erc721.mint(1, NftData { poebat: Some(String::from("uri")) }), Ok(()));
+            assert_eq!(erc721.approve(contract, 1), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+
+            assert_eq!(
+                erc721.token_info(1),
+                Ok(TokenInfo {
+                    owner: accounts.alice,
+                    metadata: NftData { poebat: Some(String::from("uri")) },
+                    approved: Some(contract),
+                    for_sale: true,
+                    price: Some(10),
+                    creator: Some(accounts.alice),
+                })
+            );
+        }
+
+        #[ink_lang::test]
+        fn approve_and_list_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.approve_and_list(1, 10), Ok(()));
+            assert_eq!(erc721.get_approved(1), Some(contract));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale { id: 1, price: 10, on_hold: false }]);
+            assert_eq!(erc721.seller_of(1), Some(accounts.alice));
+            assert_eq!(erc721.approve_and_list(1, 10), Err(Error::AlreadyForSale));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+        }
+
+        #[ink_lang::test]
+        fn burn_from_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(accounts.django, true), Ok(()));
+
+            set_caller(accounts.django);
+            assert_eq!(erc721.burn_from(accounts.alice, 1), Ok(()));
+            assert_eq!(erc721.owner_of(1), None);
+            assert_eq!(erc721.balance_of(accounts.alice), 1);
+            assert_eq!(erc721.total_supply(), 1);
+
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let event = <Event as scale::Decode>::decode(&mut &emitted_events.last().unwrap().data[..]).unwrap();
+            assert!(matches!(
+                event,
+                Event::Transfer(Transfer { from: Some(from), to: Some(to), id: 1 })
+                    if from == accounts.alice && to == AccountId::from([0x0; 32])
+            ));
+        }
+
+        #[ink_lang::test]
+        fn burn_from_without_authority_fails() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+
+            set_caller(accounts.eve);
+            assert_eq!(erc721.burn_from(accounts.alice, 1), Err(Error::NotApproved));
+            assert_eq!(erc721.burn_from(accounts.alice, 2), Err(Error::TokenNotFound));
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+        }
+
+        #[ink_lang::test]
+        fn listed_value_of_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.listed_value_of(accounts.alice), 0);
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint_to(accounts.bob, 3, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.approve_and_list(1, 10), Ok(()));
+            assert_eq!(erc721.publish_for_sale_custodial(2, 20), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.approve_and_list(3, 300), Ok(()));
+
+            assert_eq!(erc721.listed_value_of(accounts.alice), 30);
+            assert_eq!(erc721.listed_value_of(accounts.bob), 300);
+            assert_eq!(erc721.listed_value_of(accounts.charlie), 0);
+        }
+
+        #[ink_lang::test]
+        fn marketplace_events_carry_block() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            advance_blocks(5);
+            assert_eq!(erc721.approve_and_list(1, 10), Ok(()));
+            let block = ink_env::block_number::<ink_env::DefaultEnvironment>();
+            let events: Vec<_> = ink_env::test::recorded_events().collect();
+            match <Event as scale::Decode>::decode(&mut &events.last().unwrap().data[..]) {
+                Ok(Event::Listed(event)) => {
+                    assert_eq!((event.seller, event.id, event.price), (accounts.alice, 1, 10));
+                    assert_eq!(event.block, block);
+                }
+                _ => panic!("expected Listed event"),
+            }
+
+            advance_blocks(3);
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+            let events: Vec<_> = ink_env::test::recorded_events().collect();
+            match <Event as scale::Decode>::decode(&mut &events.last().unwrap().data[..]) {
+                Ok(Event::Sold(event)) => {
+                    assert_eq!((event.seller, event.buyer, event.id, event.price), (accounts.alice, accounts.bob, 1, 10));
+                    assert_eq!(event.block, block + 3);
+                    assert_eq!(event.timestamp, ink_env::block_timestamp::<ink_env::DefaultEnvironment>());
+                }
+                _ => panic!("expected Sold event"),
+            }
+        }
+
+        #[test]
+        fn checked_mul_bps_works_at_extremes() {
+            assert_eq!(checked_mul_bps(0, u16::MAX), Ok(0));
+            assert_eq!(checked_mul_bps(Balance::MAX, 0), Ok(0));
+            assert_eq!(checked_mul_bps(Balance::MAX, MAX_BPS), Ok(Balance::MAX));
+            assert_eq!(checked_mul_bps(Balance::MAX, 1), Ok(Balance::MAX / 10_000));
+            assert_eq!(checked_mul_bps(Balance::MAX, MAX_BPS + 1), Err(Error::Overflow));
+            assert_eq!(checked_mul_bps(Balance::MAX, u16::MAX), Err(Error::Overflow));
+            assert_eq!(checked_mul_bps(9_999, 1), Ok(0));
+            assert_eq!(checked_add(Balance::MAX, 1), Err(Error::Overflow));
+            assert_eq!(checked_sub(0, 1), Err(Error::Overflow));
+        }
+
+        #[ink_lang::test]
+        fn private_sale_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale_private(1, 10, accounts.bob), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 20), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale { id: 2, price: 20, on_hold: false }]);
+            assert_eq!(erc721.get_listings_paged(0, 10), vec![ForSale { id: 2, price: 20, on_hold: false }]);
+            // counts match the pages, which skip private listings
+            assert_eq!(erc721.listings_count(), 1);
+            assert_eq!(erc721.count_for_sale(), 2);
+            assert_eq!(erc721.private_buyer_of(1), Some(accounts.bob));
+
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(20);
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.buy_nft(1), Err(Error::NotWhitelisted));
+            assert_eq!(erc721.buy_nft(2), Ok(20));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            assert_eq!(erc721.private_buyer_of(1), None);
+        }
+
+        #[ink_lang::test]
+        fn private_listing_is_not_public() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 20), Ok(()));
+
+            let before = ink_env::test::recorded_events().count();
+            assert_eq!(erc721.publish_for_sale_private(1, 5, accounts.bob), Ok(()));
+            assert_eq!(ink_env::test::recorded_events().count(), before);
+            assert_eq!(erc721.floor_listing_price(), Some(20));
+            assert_eq!(erc721.update_price(1, 1), Ok(()));
+            assert_eq!(erc721.floor_listing_price(), Some(20));
+            assert_eq!(erc721.listings_count(), 1);
+
+            // the floor is recomputed from public listings only
+            assert_eq!(erc721.remove_from_sale(2), Ok(()));
+            assert_eq!(erc721.floor_listing_price(), None);
+            assert_eq!(erc721.listings_count(), 0);
+            assert_eq!(erc721.count_for_sale(), 1);
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(1);
+            assert_eq!(erc721.buy_nft(1), Ok(1));
+            assert_eq!(erc721.count_for_sale(), 0);
+            assert_eq!(erc721.listings_count(), 0);
+        }
+
+        #[ink_lang::test]
+        fn reveal_range_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_placeholder_uri(Some(String::from("ipfs://hidden"))), Ok(()));
+            for _ in 0..4 {
+                assert!(erc721.mint_next(NftData { poebat: Some(String::from("own")) }).is_ok());
+            }
+            assert_eq!(erc721.token_uri(1), Some(String::from("ipfs://hidden")));
+
+            assert_eq!(erc721.reveal_range(1, 2, String::from("ipfs://batch1/")), Ok(()));
+            assert_eq!(erc721.reveal_range(2, 3, String::from("ipfs://batch2/")), Err(Error::NotAllowed));
+            assert_eq!(erc721.reveal_range(4, 3, String::from("ipfs://batch2/")), Err(Error::NotAllowed));
+            assert_eq!(erc721.token_uri(1), Some(String::from("ipfs://batch1/1")));
+            assert_eq!(erc721.token_uri(2), Some(String::from("ipfs://batch1/2")));
+            assert_eq!(erc721.token_uri(3), Some(String::from("ipfs://hidden")));
+            assert_eq!(erc721.token_uri(5), None);
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.reveal_range(3, 4, String::from("ipfs://batch2/")), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.set_placeholder_uri(None), Ok(()));
+            assert_eq!(erc721.token_uri(3), Some(String::from("own")));
+        }
+
+        #[ink_lang::test]
+        fn royalty_cap_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.max_royalty_bps(), DEFAULT_MAX_ROYALTY_BPS);
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_royalty(1, vec![(accounts.charlie, 600), (accounts.django, 400)]), Ok(()));
+            assert_eq!(
+                erc721.set_royalty(1, vec![(accounts.charlie, 600), (accounts.django, 401)]),
+                Err(Error::RoyaltyTooHigh)
+            );
+            assert_eq!(erc721.set_default_royalty(Some((accounts.charlie, 1_001))), Err(Error::RoyaltyTooHigh));
+            assert_eq!(
+                erc721.mint_with_royalty(2, NftData { poebat: None }, accounts.charlie, 1_001),
+                Err(Error::RoyaltyTooHigh)
+            );
+            assert_eq!(erc721.royalty_info(1, 10_000), 1_000);
+
+            // lowered cap scales existing royalty down
+            assert_eq!(erc721.set_max_royalty_bps(500), Ok(()));
+            assert_eq!(erc721.royalty_recipients(1), vec![(accounts.charlie, 600), (accounts.django, 400)]);
+            assert_eq!(erc721.royalty_info(1, 10_000), 500);
+        }
+
+        #[ink_lang::test]
+        fn migrate_range_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            for id in 1..=5 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            assert_eq!(erc721.transfer(accounts.bob, 3), Ok(()));
+            let v2 = accounts.frank;
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.migrate_range(v2, 0, 2), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.migrate_range(v2, 1, 2), Ok(2));
+            assert_eq!(erc721.tokens_of_owner(v2), vec![2, 3]);
+            assert_eq!(erc721.balance_of(accounts.bob), 0);
+            assert_eq!(erc721.balance_of(accounts.alice), 3);
+
+            // clamps at the end
+            assert_eq!(erc721.migrate_range(v2, 3, 10), Ok(2));
+            assert_eq!(erc721.migrate_range(v2, 10, 10), Ok(0));
+            assert_eq!(erc721.tokens_of_owner(v2), vec![2, 3, 4, 5]);
+            assert_eq!(erc721.tokens_of_owner(accounts.alice), vec![1]);
+            assert_eq!(erc721.total_supply(), 5);
+            assert_eq!(erc721.balance_of(v2), 4);
+        }
+
+        #[ink_lang::test]
+        fn migrate_range_skips_tokens_in_custody() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            for id in 1..=3 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            assert_eq!(erc721.create_auction(2, 50, 5, None, 10), Ok(()));
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(60);
+            assert_eq!(erc721.bid(2), Ok(()));
+
+            set_caller(accounts.alice);
+            let v2 = accounts.frank;
+            assert_eq!(erc721.migrate_range(v2, 0, 3), Ok(2));
+            assert_eq!(erc721.tokens_of_owner(v2), vec![1, 3]);
+            assert_eq!(erc721.owner_of(2), Some(contract));
+
+            // the auction still settles to the bidder
+            advance_blocks(10);
+            assert_eq!(erc721.settle_auction(2), Ok(()));
+            assert_eq!(erc721.owner_of(2), Some(accounts.bob));
+        }
+
+        #[ink_lang::test]
+        fn migrate_range_skips_listed_tokens() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            for id in 1..=3 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 50), Ok(()));
+
+            let v2 = accounts.frank;
+            assert_eq!(erc721.migrate_range(v2, 0, 3), Ok(2));
+            assert_eq!(erc721.tokens_of_owner(v2), vec![1, 3]);
+            assert_eq!(erc721.owner_of(2), Some(accounts.alice));
+
+            // once delisted the token migrates too
+            assert_eq!(erc721.remove_from_sale(2), Ok(()));
+            assert_eq!(erc721.migrate_range(v2, 1, 1), Ok(1));
+            assert_eq!(erc721.owner_of(2), Some(v2));
+        }
+
+        #[ink_lang::test]
+        fn owned_with_metadata_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            for id in 1..=3 {
+                assert_eq!(erc721.mint(id, NftData { poebat: Some(ink_prelude::format!("uri{}", id)) }), Ok(()));
+            }
+            assert_eq!(erc721.mint_to(accounts.bob, 4, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(erc721.owned_with_metadata(accounts.alice, 0, 10).len(), 3);
+            assert_eq!(
+                erc721.owned_with_metadata(accounts.alice, 1, 1),
+                vec![(2, NftData { poebat: Some(String::from("uri2")) })]
+            );
+            assert_eq!(
+                erc721.owned_with_metadata(accounts.alice, 2, 10),
+                vec![(3, NftData { poebat: Some(String::from("uri3")) })]
+            );
+            assert_eq!(erc721.owned_with_metadata(accounts.charlie, 0, 10), vec![]);
+        }
+
+        #[ink_lang::test]
+        fn recover_listing_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.publish_for_sale_custodial(1, 50), Ok(()));
+
+            // simulate a buy which dropped the listing but left the token in custody
+            assert_eq!(erc721.remove_listing(1), Ok(()));
+            erc721.sellers.insert(1, &accounts.bob);
+            assert_eq!(erc721.owner_of(1), Some(contract));
+
+            assert_eq!(erc721.recover_listing(1), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.recover_listing(1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            assert_eq!(erc721.seller_of(1), None);
+            assert_eq!(erc721.recover_listing(1), Err(Error::NotAllowed));
+        }
+
+        #[ink_lang::test]
+        fn transfer_hook_is_notified() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let hook = AccountId::from([0xa1; 32]);
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_transfer_hook(Some(hook)), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.set_transfer_hook(Some(hook)), Ok(()));
+            assert_eq!(erc721.transfer_hook(), Some(hook));
+
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            assert_eq!(TransferHookMock::calls(hook), vec![(accounts.alice, accounts.bob, 1)]);
+        }
+
+        #[ink_lang::test]
+        fn failing_transfer_hook_does_not_revert_transfer() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let hook = AccountId::from([0xa2; 32]);
+            TransferHookMock::fail(hook);
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_transfer_hook(Some(hook)), Ok(()));
+
+            // the mock fails without consuming gas; a hook burning its gas is bounded by
+            // `transfer_hook_gas_limit` on-chain, which the off-chain environment cannot show
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            assert_eq!(TransferHookMock::calls(hook), vec![]);
+        }
+
+        #[ink_lang::test]
+        fn set_transfer_hook_gas_limit_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.transfer_hook_gas_limit(), DEFAULT_TRANSFER_HOOK_GAS_LIMIT);
+            assert_eq!(erc721.set_transfer_hook_gas_limit(0), Err(Error::NotAllowed));
+            assert_eq!(erc721.set_transfer_hook_gas_limit(MAX_TRANSFER_HOOK_GAS_LIMIT + 1), Err(Error::NotAllowed));
+            assert_eq!(erc721.set_transfer_hook_gas_limit(1_000_000), Ok(()));
+            assert_eq!(erc721.transfer_hook_gas_limit(), 1_000_000);
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_transfer_hook_gas_limit(1), Err(Error::NotOwner));
+        }
+
+        #[ink_lang::test]
+        fn transfer_to_contract_fails() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(erc721.transfer(contract, 1), Err(Error::NotAllowed));
+            assert_eq!(erc721.transfer_from(accounts.alice, contract, 1), Err(Error::NotAllowed));
+            assert_eq!(erc721.mint_to(contract, 2, NftData { poebat: None }), Err(Error::NotAllowed));
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+
+            // custodial listing still moves token to the contract
+            assert_eq!(erc721.publish_for_sale_custodial(1, 50), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(contract));
+        }
+
+        #[ink_lang::test]
+        fn self_transfer_fails() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            let events_before = ink_env::test::recorded_events().count();
+
+            assert_eq!(erc721.transfer(accounts.alice, 1), Err(Error::SelfTransfer));
+            assert_eq!(erc721.transfer_from(accounts.alice, accounts.alice, 1), Err(Error::SelfTransfer));
+            assert_eq!(erc721.balance_of(accounts.alice), 1);
+            assert_eq!(erc721.tokens_of_owner(accounts.alice), vec![1]);
+            assert_eq!(ink_env::test::recorded_events().count(), events_before);
+        }
+
+        #[ink_lang::test]
+        fn psp22_payment_token_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let token = AccountId::from([0xdd; 32]);
+            Psp22Mock::mint(token, accounts.bob, 1_000);
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_payment_token(Some(token)), Ok(()));
+            assert_eq!(erc721.set_fee_bps(250), Ok(()));
+            assert_eq!(erc721.mint_with_royalty(1, NftData { poebat: None }, accounts.charlie, 1_000), Ok(()));
+            assert_eq!(erc721.approve_and_list(1, 1_000), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(1_000);
+            assert_eq!(erc721.buy_nft(1), Err(Error::NotAllowed));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(0);
+            assert_eq!(erc721.buy_nft(1), Ok(1_000));
+            assert_eq!(Psp22Mock::balance_of(token, accounts.bob), 0);
+            assert_eq!(Psp22Mock::balance_of(token, accounts.alice), 875);
+            assert_eq!(Psp22Mock::balance_of(token, accounts.charlie), 100);
+            assert_eq!(Psp22Mock::balance_of(token, contract), 25);
+            // native balances are untouched
+            assert_eq!(get_balance(accounts.alice), 1_000_000);
+            assert_eq!(get_balance(accounts.charlie), 1_000);
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.accrued_fees(), 0);
+            assert_eq!(erc721.accrued_token_fees(), 25);
+            assert_eq!(erc721.set_payment_token(None), Err(Error::NotAllowed));
+            assert_eq!(erc721.withdraw_token_fees(accounts.django), Ok(25));
+            assert_eq!(Psp22Mock::balance_of(token, accounts.django), 25);
+            assert_eq!(Psp22Mock::balance_of(token, contract), 0);
+            assert_eq!(erc721.set_payment_token(None), Ok(()));
+        }
+
+        #[ink_lang::test]
+        fn native_sales_ignore_payment_token() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let token = AccountId::from([0xdd; 32]);
+            Psp22Mock::mint(token, contract, 1_000);
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_payment_token(Some(token)), Ok(()));
+            assert_eq!(erc721.set_fee_bps(250), Ok(()));
+            assert_eq!(erc721.mint_with_royalty(1, NftData { poebat: None }, accounts.charlie, 1_000), Ok(()));
+            assert_eq!(erc721.start_dutch_auction(1, 400, 400, 10), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(400);
+            assert_eq!(erc721.buy_dutch(1), Ok(()));
+            // the sale is paid in native balance it collected, the token pool of the contract is untouched
+            assert_eq!(get_balance(accounts.alice), 1_000_000 + 350);
+            assert_eq!(get_balance(accounts.charlie), 1_000 + 40);
+            assert_eq!(Psp22Mock::balance_of(token, contract), 1_000);
+            assert_eq!(Psp22Mock::balance_of(token, accounts.alice), 0);
+            assert_eq!(erc721.accrued_fees(), 10);
+            assert_eq!(erc721.accrued_token_fees(), 0);
+
+            // native fees do not block switching the payment token and are withdrawn natively
+            set_caller(accounts.alice);
+            assert_eq!(erc721.set_payment_token(None), Ok(()));
+            assert_eq!(erc721.withdraw_fees(accounts.django), Ok(10));
+            assert_eq!(get_balance(accounts.django), 10);
+        }
+
+        #[ink_lang::test]
+        fn double_listing_fails() {
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            assert_eq!(erc721.publish_for_sale(1, 20), Err(Error::AlreadyForSale));
+            assert_eq!(erc721.approve_and_list(1, 20), Err(Error::AlreadyForSale));
+            assert_eq!(erc721.publish_for_sale_custodial(1, 20), Err(Error::AlreadyForSale));
+            assert_eq!(erc721.listings_count(), 1);
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale { id: 1, price: 10, on_hold: false }]);
+        }
+
+        #[ink_lang::test]
+        fn update_metadata_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.add_minter(accounts.bob), Ok(()));
+            assert_eq!(erc721.mint(1, NftData { poebat: Some(String::from("ipfs://tpyo")) }), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.update_metadata(1, NftData { poebat: Some(String::from("ipfs://typo")) }), Ok(()));
+            assert_eq!(erc721.token_uri(1), Some(String::from("ipfs://typo")));
+            let emitted_events = ink_env::test::recorded_events().collect::<Vec<_>>();
+            let event = <Event as scale::Decode>::decode(&mut &emitted_events.last().unwrap().data[..]).unwrap();
+            assert!(matches!(event, Event::MetadataUpdated(MetadataUpdated { id: 1 })));
+        }
+
+        #[ink_lang::test]
+        fn update_metadata_fails_frozen_or_unauthorized() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.freeze_metadata(2), Ok(()));
+            assert_eq!(erc721.update_metadata(2, NftData { poebat: None }), Err(Error::MetadataFrozen));
+
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.update_metadata(1, NftData { poebat: Some(String::from("x")) }), Err(Error::NotAllowed));
+            assert_eq!(erc721.get_nft_info(1), Ok(NftData { poebat: None }));
+        }
+
+        #[ink_lang::test]
+        fn hold_listing_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.hold_listing(1), Err(Error::NotForSale));
+            assert_eq!(erc721.approve_and_list(1, 10), Ok(()));
+            assert_eq!(erc721.hold_listing(1), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale { id: 1, price: 10, on_hold: true }]);
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.release_listing(1), Err(Error::NotApproved));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.buy_nft(1), Err(Error::ListingOnHold));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.release_listing(1), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale { id: 1, price: 10, on_hold: false }]);
+            set_caller(accounts.bob);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+        }
+
+        #[ink_lang::test]
+        fn owned_tokens_match_balance() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let holders = [accounts.alice, accounts.bob, accounts.charlie];
+            let mut erc721 = Erc721::new();
+            let in_sync = |erc721: &Erc721| {
+                holders
+                    .iter()
+                    .all(|holder| erc721.tokens_of_owner(*holder).len() as u32 == erc721.balance_of(*holder))
+            };
+
+            for id in 1..=30 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+                assert!(in_sync(&erc721));
+            }
+            for id in 1..=30 {
+                let to = holders[(id % 3) as usize];
+                if to != accounts.alice {
+                    assert_eq!(erc721.transfer(to, id), Ok(()));
+                }
+                assert!(in_sync(&erc721));
+            }
+            for id in (1..=30).filter(|id| id % 3 == 0 || id % 5 == 0) {
+                let owner = erc721.owner_of(id).unwrap();
+                set_caller(owner);
+                assert_eq!(erc721.burn(id), Ok(()));
+                assert!(in_sync(&erc721));
+            }
+            assert!(erc721.verify_invariants(holders.to_vec()));
+        }
+
+        #[ink_lang::test]
+        fn invariants_hold() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            for id in 1..=4 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            assert_eq!(erc721.burn(2), Ok(()));
+            assert_eq!(erc721.transfer(accounts.bob, 3), Ok(()));
+            assert_eq!(erc721.mint_to(accounts.charlie, 5, NftData { poebat: None }), Ok(()));
+
+            assert!(erc721.verify_invariants(vec![accounts.alice, accounts.bob, accounts.charlie]));
+            // missing or repeated holders break the sum
+            assert!(!erc721.verify_invariants(vec![accounts.alice, accounts.bob]));
+            assert!(!erc721.verify_invariants(vec![accounts.alice, accounts.bob, accounts.charlie, accounts.bob]));
+        }
+
+        #[ink_lang::test]
+        fn get_all_tokens_works() {
+            let accounts =
+                ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            // Create a new contract instance.
+            let mut erc721 = Erc721::new();
+            // no token exists
+            assert_eq!(erc721.get_all_tokens(), vec![]);
+            // Create tokens
+            assert_eq!(erc721.mint(1, NftData{poebat: Some("1".to_string())}), Ok(()));
+            assert_eq!(erc721.mint(2, NftData{poebat: Some("2".to_string())}), Ok(()));
+
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
+            assert_eq!(erc721.mint(3, NftData{poebat: Some("3".to_string())}), Ok(()));
+
+            // exists 3 tokens
+            assert_eq!(erc721.get_all_tokens(), vec![(1, NftData{poebat: Some("1".to_string())}), (2, NftData{poebat: Some("2".to_string())}), (3, NftData{poebat: Some("3".to_string())})]);
+            // burn token
+            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
+            assert_eq!(erc721.burn(2), Ok(()));
+            // exists 2 tokens
+            assert_eq!(erc721.get_all_tokens(), vec![(1, NftData{poebat: Some("1".to_string())}), (3, NftData{poebat: Some("3".to_string())})]);
+        }
+
+        #[ink_lang::test]
+        fn tokens_of_owner_works() {
+            let accounts =
+                ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            // Create a new contract instance.
             let mut erc721 = Erc721::new();
             // Token 1 does not exists.
             assert_eq!(erc721.owner_of(1), None);
             // Alice does not owns tokens.
-            assert_eq!(erc721.balance_of(accounts.alice), 0);
+            assert_eq!(erc721.tokens_of_owner(accounts.alice).len(), 0);
+            // Create tokens
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+            // Alice owns 1 token.
+            assert_eq!(erc721.tokens_of_owner(accounts.alice), vec![1, 2, 3]);
+        }
+
+        #[ink_lang::test]
+        fn mint_existing_should_fail() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            // Create a new contract instance.
+            let mut erc721 = Erc721::new();
             // Create token Id 1.
             assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            // The first Transfer event takes place
+            assert_eq!(1, ink_env::test::recorded_events().count());
             // Alice owns 1 token.
             assert_eq!(erc721.balance_of(accounts.alice), 1);
+            // Alice owns token Id 1.
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+            // Cannot create  token Id if it exists.
+            // Bob cannot own token Id 1.
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Err(Error::TokenExists));
         }
-        
+
+        #[ink_lang::test]
+        fn transfer_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            // Create a new contract instance.
+            let mut erc721 = Erc721::new();
+            // Create token Id 1 for Alice
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            // Alice owns token 1
+            assert_eq!(erc721.balance_of(accounts.alice), 1);
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+            // Bob does not owns any token
+            assert_eq!(erc721.balance_of(accounts.bob), 0);
+            // The first Transfer event takes place
+            assert_eq!(1, ink_env::test::recorded_events().count());
+            // Alice transfers token 1 to Bob
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            // The second Transfer event takes place
+            assert_eq!(2, ink_env::test::recorded_events().count());
+            // Bob owns token 1
+            assert_eq!(erc721.balance_of(accounts.bob), 1);
+        }
+
+        #[ink_lang::test]
+        fn composite_token_id_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            // series 7, serial 1
+            let id: TokenId = (7 << 64) | 1;
+            assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.owner_of(id), Some(accounts.alice));
+            assert_eq!(erc721.owner_of(1), None);
+            assert_eq!(erc721.tokens_of_owner(accounts.alice), vec![id]);
+            assert_eq!(erc721.storage_version(), STORAGE_VERSION);
+        }
+
+        #[ink_lang::test]
+        fn try_owner_of_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            // Existing token
+            assert_eq!(erc721.try_owner_of(1), Ok(accounts.alice));
+            // Missing token
+            assert_eq!(erc721.try_owner_of(2), Err(Error::TokenNotFound));
+            assert_eq!(erc721.owner_of(2), None);
+        }
+
+        #[ink_lang::test]
+        fn check_owner_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            // owner
+            assert!(erc721.check_owner(accounts.alice, 1));
+            // wrong account
+            assert!(!erc721.check_owner(accounts.bob, 1));
+            // not existing token
+            assert!(!erc721.check_owner(accounts.alice, 2));
+            // zero address never owns anything
+            assert!(!erc721.check_owner(AccountId::from([0x0; 32]), 2));
+        }
+
+        #[ink_lang::test]
+        fn transfer_with_memo_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(
+                erc721.transfer_with_memo(accounts.bob, 1, vec![0; MAX_MEMO_LEN + 1]),
+                Err(Error::MemoTooLong)
+            );
+            assert_eq!(erc721.transfer_with_memo(accounts.bob, 1, b"invoice-42".to_vec()), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+
+            // mint, transfer and memo events
+            let events: Vec<_> = ink_env::test::recorded_events().collect();
+            assert_eq!(events.len(), 3);
+            match <Event as scale::Decode>::decode(&mut &events[2].data[..]) {
+                Ok(Event::TransferWithMemo(event)) => {
+                    assert_eq!(event.from, Some(accounts.alice));
+                    assert_eq!(event.to, Some(accounts.bob));
+                    assert_eq!(event.id, 1);
+                    assert_eq!(event.memo, b"invoice-42".to_vec());
+                }
+                _ => panic!("expected TransferWithMemo event"),
+            }
+        }
+
+        #[ink_lang::test]
+        fn approved_transfer_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+
+            // not approved account cannot transfer
+            set_caller(accounts.bob);
+            assert_eq!(erc721.transfer_from(accounts.alice, accounts.frank, 1), Err(Error::NotApproved));
+
+            // approved for one token
+            set_caller(accounts.alice);
+            assert_eq!(erc721.approve(accounts.bob, 1), Ok(()));
+            assert_eq!(erc721.get_approved(1), Some(accounts.bob));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.transfer_from(accounts.alice, accounts.frank, 1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.frank));
+            // approval is cleared on transfer
+            assert_eq!(erc721.get_approved(1), None);
+
+            // approved for all tokens
+            set_caller(accounts.alice);
+            assert_eq!(erc721.set_approval_for_all(accounts.bob, true), Ok(()));
+            assert!(erc721.is_approved_for_all(accounts.alice, accounts.bob));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.transfer_from(accounts.alice, accounts.frank, 2), Ok(()));
+            assert_eq!(erc721.balance_of(accounts.frank), 2);
+        }
+
+        #[ink_lang::test]
+        fn batch_approve_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            for id in 1..=3 {
+                assert_eq!(erc721.mint(id, NftData { poebat: None }), Ok(()));
+            }
+            let events_before = ink_env::test::recorded_events().count();
+
+            assert_eq!(erc721.batch_approve(accounts.bob, vec![1, 2, 3]), Ok(()));
+            for id in 1..=3 {
+                assert_eq!(erc721.get_approved(id), Some(accounts.bob));
+            }
+            // approval event per token
+            assert_eq!(ink_env::test::recorded_events().count(), events_before + 3);
+        }
+
+        #[ink_lang::test]
+        fn batch_approve_fails_not_owned() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            set_caller(accounts.eve);
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.batch_approve(accounts.bob, vec![1, 3, 2]), Err(Error::NotOwner));
+            // none were approved
+            for id in 1..=3 {
+                assert_eq!(erc721.get_approved(id), None);
+            }
+        }
+
+        #[ink_lang::test]
+        fn permit_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            let (key, owner) = keypair(1);
+            set_caller(owner);
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+
+            // relayer submits signed approval
+            set_caller(accounts.eve);
+            let signature = sign(&key, erc721.permit_hash(owner, accounts.bob, 1, 10));
+            assert_eq!(erc721.permit(owner, accounts.bob, 1, 10, signature), Ok(()));
+            assert_eq!(erc721.get_approved(1), Some(accounts.bob));
+            assert_eq!(erc721.nonce_of(owner), 1);
+
+            // signature of another key
+            let (other_key, _) = keypair(2);
+            let signature = sign(&other_key, erc721.permit_hash(owner, accounts.bob, 2, 10));
+            assert_eq!(erc721.permit(owner, accounts.bob, 2, 10, signature), Err(Error::InvalidSignature));
+            assert_eq!(erc721.get_approved(2), None);
+        }
+
+        #[ink_lang::test]
+        fn permit_fails_expired_or_replayed() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            let (key, owner) = keypair(1);
+            set_caller(owner);
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+
+            set_caller(accounts.eve);
+            // expired
+            let signature = sign(&key, erc721.permit_hash(owner, accounts.bob, 1, 2));
+            advance_blocks(3);
+            assert_eq!(erc721.permit(owner, accounts.bob, 1, 2, signature), Err(Error::PermitExpired));
+
+            // replayed
+            let signature = sign(&key, erc721.permit_hash(owner, accounts.bob, 1, 10));
+            assert_eq!(erc721.permit(owner, accounts.bob, 1, 10, signature), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.transfer_from(owner, accounts.bob, 1), Ok(()));
+            set_caller(accounts.eve);
+            assert_eq!(erc721.permit(owner, accounts.bob, 1, 10, signature), Err(Error::InvalidSignature));
+        }
+
         #[ink_lang::test]
-        fn publish_for_sale_works() {
+        fn redeem_voucher_works() {
             let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
-            // Create a new contract instance.
+            set_contract_account();
             let mut erc721 = Erc721::new();
+            let (key, creator) = keypair(1);
+            assert_eq!(erc721.add_minter(creator), Ok(()));
+            let voucher = || MintVoucher {
+                id: None,
+                metadata: NftData { poebat: Some(String::from("uri")) },
+                min_price: 100,
+                creator,
+                nonce: 0,
+            };
+            let signature = sign(&key, erc721.voucher_hash(voucher()));
 
-            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
-            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
-            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(99);
+            assert_eq!(erc721.redeem_voucher(voucher(), signature), Err(Error::NotEnoughSent));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(100);
+            assert_eq!(erc721.redeem_voucher(voucher(), signature), Ok(1));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+            assert_eq!(erc721.creator_of(1), Some(creator));
+            assert_eq!(erc721.get_nft_info(1), Ok(NftData { poebat: Some(String::from("uri")) }));
+            assert_eq!(get_balance(creator), 100);
+        }
 
-            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
-            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 1, price: 10}]);
+        #[ink_lang::test]
+        fn redeem_voucher_fails_bad_signature_or_replayed() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
+            let mut erc721 = Erc721::new();
+            let (key, creator) = keypair(1);
+            let (other_key, _) = keypair(2);
+            assert_eq!(erc721.add_minter(creator), Ok(()));
+            let voucher = || MintVoucher { id: Some(7), metadata: NftData { poebat: None }, min_price: 0, creator, nonce: 3 };
 
-            assert_eq!(erc721.publish_for_sale(2, 100), Ok(()));
-            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 1, price: 10}, ForSale{id: 2, price: 100}]);
+            set_caller(accounts.bob);
+            let signature = sign(&other_key, erc721.voucher_hash(voucher()));
+            assert_eq!(erc721.redeem_voucher(voucher(), signature), Err(Error::InvalidSignature));
+            assert_eq!(erc721.owner_of(7), None);
 
-            assert_eq!(erc721.remove_from_sale(1), Ok(()));
-            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 2, price: 100}]);
+            let signature = sign(&key, erc721.voucher_hash(voucher()));
+            assert_eq!(erc721.redeem_voucher(voucher(), signature), Ok(7));
+            set_caller(accounts.charlie);
+            assert_eq!(erc721.redeem_voucher(voucher(), signature), Err(Error::VoucherRedeemed));
+            assert_eq!(erc721.owner_of(7), Some(accounts.bob));
         }
 
         #[ink_lang::test]
-        fn buy_nft_works() {
+        fn blacklist_blocks_transfers() {
             let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
-            let mut erc721 = Erc721::new();
-
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new_with_blacklist();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
             assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.transfer(accounts.bob, 2), Ok(()));
 
-            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
-            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 1, price: 10}]);
+            set_caller(accounts.bob);
+            assert_eq!(erc721.add_to_blacklist(accounts.eve), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.add_to_blacklist(accounts.eve), Ok(()));
+            assert_eq!(erc721.add_to_blacklist(accounts.bob), Ok(()));
+            assert!(erc721.is_blacklisted(accounts.eve));
 
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
-            assert_eq!(erc721.is_owner_of(Some(accounts.alice), 1), true);
-            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(accounts.bob, 10);
+            // blocked recipient
+            assert_eq!(erc721.transfer(accounts.eve, 1), Err(Error::Blacklisted));
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+            // blocked sender
+            set_caller(accounts.bob);
+            assert_eq!(erc721.transfer(accounts.charlie, 2), Err(Error::Blacklisted));
+            assert_eq!(erc721.owner_of(2), Some(accounts.bob));
+            // blocked buyer
+            set_caller(accounts.alice);
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            set_caller(accounts.eve);
             ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
-            assert_eq!(erc721.buy_nft(1), Ok(()));
-            
-            assert_eq!(erc721.get_tokens_for_sale(), vec![]);
-            assert_eq!(erc721.is_owner_of(Some(accounts.bob), 1), true);
+            assert_eq!(erc721.buy_nft(1), Err(Error::Blacklisted));
 
+            set_caller(accounts.alice);
+            assert_eq!(erc721.remove_from_blacklist(accounts.eve), Ok(()));
+            assert_eq!(erc721.remove_from_sale(1), Ok(()));
+            assert_eq!(erc721.transfer(accounts.eve, 1), Ok(()));
         }
 
         #[ink_lang::test]
-        fn get_all_tokens_works() {
-            let accounts =
-                ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
-            // Create a new contract instance.
+        fn blacklist_disabled_passes_through() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
             let mut erc721 = Erc721::new();
-            // no token exists
-            assert_eq!(erc721.get_all_tokens(), vec![]);
-            // Create tokens
-            assert_eq!(erc721.mint(1, NftData{poebat: Some("1".to_string())}), Ok(()));
-            assert_eq!(erc721.mint(2, NftData{poebat: Some("2".to_string())}), Ok(()));
-
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.bob);
-            assert_eq!(erc721.mint(3, NftData{poebat: Some("3".to_string())}), Ok(()));
+            assert!(!erc721.blacklist_enabled());
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
 
-            // exists 3 tokens
-            assert_eq!(erc721.get_all_tokens(), vec![(1, NftData{poebat: Some("1".to_string())}), (2, NftData{poebat: Some("2".to_string())}), (3, NftData{poebat: Some("3".to_string())})]);
-            // burn token
-            ink_env::test::set_caller::<ink_env::DefaultEnvironment>(accounts.alice);
-            assert_eq!(erc721.burn(2), Ok(()));
-            // exists 2 tokens
-            assert_eq!(erc721.get_all_tokens(), vec![(1, NftData{poebat: Some("1".to_string())}), (3, NftData{poebat: Some("3".to_string())})]);
+            assert_eq!(erc721.add_to_blacklist(accounts.eve), Err(Error::NotAllowed));
+            assert_eq!(erc721.transfer(accounts.eve, 1), Ok(()));
         }
 
         #[ink_lang::test]
-        fn tokens_of_owner_works() {
-            let accounts =
-                ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
-            // Create a new contract instance.
+        fn transfer_cooldown_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            set_contract_account();
             let mut erc721 = Erc721::new();
-            // Token 1 does not exists.
-            assert_eq!(erc721.owner_of(1), None);
-            // Alice does not owns tokens.
-            assert_eq!(erc721.tokens_of_owner(accounts.alice).len(), 0);
-            // Create tokens
+            assert_eq!(erc721.set_cooldown_blocks(Some(5)), Ok(()));
             assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
-            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
-            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
-            // Alice owns 1 token.
-            assert_eq!(erc721.tokens_of_owner(accounts.alice), vec![1, 2, 3]);
+
+            // minting starts the cooldown
+            assert_eq!(erc721.transfer(accounts.bob, 1), Err(Error::CooldownActive));
+            advance_blocks(4);
+            assert_eq!(erc721.transfer(accounts.bob, 1), Err(Error::CooldownActive));
+            advance_blocks(1);
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+
+            // transfer restarts the cooldown
+            set_caller(accounts.bob);
+            assert_eq!(erc721.transfer(accounts.charlie, 1), Err(Error::CooldownActive));
+            advance_blocks(5);
+            assert_eq!(erc721.transfer(accounts.charlie, 1), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(accounts.charlie));
         }
 
         #[ink_lang::test]
-        fn mint_existing_should_fail() {
+        fn cooldown_does_not_block_custodial_sale() {
             let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
-            // Create a new contract instance.
+            let contract = set_contract_account();
             let mut erc721 = Erc721::new();
-            // Create token Id 1.
+            assert_eq!(erc721.set_cooldown_blocks(Some(5)), Ok(()));
             assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
-            // The first Transfer event takes place
-            assert_eq!(1, ink_env::test::recorded_events().count());
-            // Alice owns 1 token.
-            assert_eq!(erc721.balance_of(accounts.alice), 1);
-            // Alice owns token Id 1.
-            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
-            // Cannot create  token Id if it exists.
-            // Bob cannot own token Id 1.
-            assert_eq!(erc721.mint(1, NftData { poebat: None }), Err(Error::TokenExists));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(erc721.publish_for_sale_custodial(1, 10), Ok(()));
+            assert_eq!(erc721.owner_of(1), Some(contract));
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.buy_nft(1), Ok(10));
+            assert_eq!(erc721.owner_of(1), Some(accounts.bob));
+
+            // a custodial listing can be withdrawn in the same block too
+            set_caller(accounts.alice);
+            assert_eq!(erc721.publish_for_sale_custodial(2, 10), Ok(()));
+            assert_eq!(erc721.remove_from_sale(2), Ok(()));
+            assert_eq!(erc721.owner_of(2), Some(accounts.alice));
+            // custody moves did not restart the cooldown started by minting
+            advance_blocks(5);
+            assert_eq!(erc721.transfer(accounts.charlie, 2), Ok(()));
+        }
+
+        /// Staking contract mock which uses the collection only through `Erc721Core`.
+        struct StakingMock {
+            vault: AccountId,
+            staked: Vec<(AccountId, TokenId)>,
+        }
+
+        impl StakingMock {
+            fn stake<T>(&mut self, nft: &mut T, staker: AccountId, id: TokenId) -> Result<(), Error>
+            where
+                T: crate::Erc721Core<ownerOfOutput = Option<AccountId>, transferFromOutput = Result<(), Error>>,
+            {
+                if nft.owner_of(id) != Some(staker) {
+                    return Err(Error::NotOwner);
+                }
+                nft.transfer_from(staker, self.vault, id)?;
+                self.staked.push((staker, id));
+                Ok(())
+            }
         }
 
         #[ink_lang::test]
-        fn transfer_works() {
+        fn erc721_core_trait_works() {
             let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
-            // Create a new contract instance.
             let mut erc721 = Erc721::new();
-            // Create token Id 1 for Alice
+            let mut staking = StakingMock { vault: accounts.django, staked: Vec::new() };
             assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
-            // Alice owns token 1
-            assert_eq!(erc721.balance_of(accounts.alice), 1);
-            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
-            // Bob does not owns any token
-            assert_eq!(erc721.balance_of(accounts.bob), 0);
-            // The first Transfer event takes place
-            assert_eq!(1, ink_env::test::recorded_events().count());
-            // Alice transfers token 1 to Bob
-            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
-            // The second Transfer event takes place
-            assert_eq!(2, ink_env::test::recorded_events().count());
-            // Bob owns token 1
-            assert_eq!(erc721.balance_of(accounts.bob), 1);
+            assert_eq!(crate::Erc721Core::approve(&mut erc721, accounts.django, 1), Ok(()));
+
+            // the staking contract calls as the vault
+            set_caller(accounts.django);
+            assert_eq!(staking.stake(&mut erc721, accounts.bob, 1), Err(Error::NotOwner));
+            assert_eq!(staking.stake(&mut erc721, accounts.alice, 1), Ok(()));
+
+            assert_eq!(staking.staked, vec![(accounts.alice, 1)]);
+            assert_eq!(crate::Erc721Core::owner_of(&erc721, 1), Some(accounts.django));
+            assert_eq!(crate::Erc721Core::balance_of(&erc721, accounts.django), 1);
         }
 
         #[ink_lang::test]
@@ -594,6 +6984,118 @@ mod erc721 {
             );
         }
 
+        #[ink_lang::test]
+        fn get_nft_info_batch_works() {
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: Some("1".to_string()) }), Ok(()));
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(
+                erc721.get_nft_info_batch(vec![1, 2, 3]),
+                vec![
+                    (1, Some(NftData { poebat: Some("1".to_string()) })),
+                    (2, None),
+                    (3, Some(NftData { poebat: None })),
+                ]
+            );
+            // input is capped
+            let ids = (0..(MAX_BATCH_SIZE as TokenId + 10)).collect();
+            assert_eq!(erc721.get_nft_info_batch(ids).len(), MAX_BATCH_SIZE);
+        }
+
+        #[ink_lang::test]
+        fn mint_with_cid_works() {
+            let mut erc721 = Erc721::new();
+            // sha2-256 multihash of "hello"
+            let mut cid = vec![0x12, 0x20];
+            cid.extend_from_slice(&[
+                44, 242, 77, 186, 95, 176, 163, 14, 38, 232, 59, 42, 197, 185, 226, 158, 27, 22, 30, 92,
+                31, 167, 66, 94, 115, 4, 51, 98, 147, 139, 152, 36,
+            ]);
+
+            assert_eq!(erc721.mint_with_cid(1, cid.clone()), Ok(()));
+            assert_eq!(
+                erc721.token_uri(1),
+                Some("ipfs://QmRN6wdp1S2A5EtjW9A3M1vKSBuQQGcgvuhoMUoEz4iiT5".to_string())
+            );
+
+            // truncated CID is rejected
+            assert_eq!(erc721.mint_with_cid(2, cid[..CID_LEN - 1].to_vec()), Err(Error::CannotParseMetadata));
+            assert_eq!(erc721.owner_of(2), None);
+            // not a sha2-256 multihash
+            cid[0] = 0x00;
+            assert_eq!(erc721.mint_with_cid(2, cid), Err(Error::CannotParseMetadata));
+
+            // plain metadata string is returned as is
+            assert_eq!(erc721.mint(3, NftData { poebat: Some("https://nft/3".to_string()) }), Ok(()));
+            assert_eq!(erc721.token_uri(3), Some("https://nft/3".to_string()));
+            assert_eq!(erc721.token_uri(4), None);
+        }
+
+        #[ink_lang::test]
+        fn freeze_all_metadata_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_attribute(1, "rarity".to_string(), "common".to_string()), Ok(()));
+            assert_eq!(erc721.set_base_uri(Some("ipfs://base/".to_string())), Ok(()));
+
+            set_caller(accounts.bob);
+            assert_eq!(erc721.freeze_all_metadata(), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.freeze_all_metadata(), Ok(()));
+            assert!(erc721.is_collection_frozen());
+            assert!(erc721.is_metadata_frozen(1));
+
+            assert_eq!(erc721.update_metadata(1, NftData { poebat: None }), Err(Error::MetadataFrozen));
+            assert_eq!(erc721.set_token_uri(1, "ipfs://changed".to_string()), Err(Error::MetadataFrozen));
+            assert_eq!(
+                erc721.set_attribute(1, "rarity".to_string(), "legendary".to_string()),
+                Err(Error::MetadataFrozen)
+            );
+            assert_eq!(erc721.set_base_uri(None), Err(Error::MetadataFrozen));
+            assert_eq!(erc721.set_placeholder_uri(Some("ipfs://hidden".to_string())), Err(Error::MetadataFrozen));
+            assert_eq!(erc721.reveal_range(1, 2, "ipfs://revealed/".to_string()), Err(Error::MetadataFrozen));
+
+            // reads still work
+            assert_eq!(erc721.token_uri(1), Some("ipfs://base/1".to_string()));
+            assert_eq!(erc721.get_attribute(1, "rarity".to_string()), Some("common".to_string()));
+            assert_eq!(erc721.base_uri(), Some("ipfs://base/".to_string()));
+        }
+
+        #[ink_lang::test]
+        fn freeze_metadata_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_token_uri(1, "ipfs://hidden".to_string()), Ok(()));
+            assert_eq!(erc721.set_attribute(1, "rarity".to_string(), "common".to_string()), Ok(()));
+
+            // only creator changes metadata
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_token_uri(1, "ipfs://fake".to_string()), Err(Error::NotAllowed));
+            assert_eq!(erc721.freeze_metadata(1), Err(Error::NotAllowed));
+
+            // reveal and freeze
+            set_caller(accounts.alice);
+            assert_eq!(erc721.set_token_uri(1, "ipfs://revealed".to_string()), Ok(()));
+            assert_eq!(erc721.transfer(accounts.bob, 1), Ok(()));
+            set_caller(accounts.bob);
+            assert_eq!(erc721.freeze_metadata(1), Ok(()));
+            assert!(erc721.is_metadata_frozen(1));
+
+            set_caller(accounts.alice);
+            assert_eq!(erc721.set_token_uri(1, "ipfs://changed".to_string()), Err(Error::MetadataFrozen));
+            assert_eq!(
+                erc721.set_attribute(1, "rarity".to_string(), "legendary".to_string()),
+                Err(Error::MetadataFrozen)
+            );
+
+            // reads still work
+            assert_eq!(erc721.token_uri(1), Some("ipfs://revealed".to_string()));
+            assert_eq!(erc721.get_attribute(1, "rarity".to_string()), Some("common".to_string()));
+        }
+
         #[ink_lang::test]
         fn burn_works() {
             let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
@@ -633,8 +7135,259 @@ mod erc721 {
             assert_eq!(erc721.burn(1), Err(Error::NotOwner));
         }
 
+        #[ink_lang::test]
+        fn buy_bundle_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+
+            assert_eq!(erc721.publish_bundle_for_sale(vec![1, 2], 20), Ok(0));
+            assert_eq!(erc721.get_bundle(0), Some((vec![1, 2], 20)));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(10);
+            assert_eq!(erc721.buy_bundle(0), Err(Error::NotEnoughSent));
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(20);
+            assert_eq!(erc721.buy_bundle(0), Ok(()));
+
+            assert_eq!(erc721.tokens_of_owner(accounts.bob), vec![1, 2]);
+            assert_eq!(erc721.tokens_of_owner(accounts.alice), vec![3]);
+            assert_eq!(erc721.get_bundle(0), None);
+            assert_eq!(erc721.buy_bundle(0), Err(Error::NotForSale));
+        }
+
+        #[ink_lang::test]
+        fn publish_bundle_for_sale_checks_tokens_like_listing() {
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+
+            assert_eq!(erc721.publish_bundle_for_sale(vec![1, 2], 20), Err(Error::MarketplaceNotApproved));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_bundle_for_sale(vec![1, 2], 0), Err(Error::InvalidPrice));
+            assert_eq!(erc721.set_floor_price(Some(50)), Ok(()));
+            assert_eq!(erc721.publish_bundle_for_sale(vec![1, 2], 20), Err(Error::BelowFloor));
+            assert_eq!(erc721.publish_bundle_for_sale(vec![1, 3], 50), Err(Error::TokenNotFound));
+            assert_eq!(erc721.publish_bundle_for_sale(vec![1; MAX_BATCH_SIZE + 1], 50), Err(Error::NotAllowed));
+            assert_eq!(erc721.get_bundle(0), None);
+            assert_eq!(erc721.publish_bundle_for_sale(vec![1, 2], 50), Ok(0));
+        }
+
+        #[ink_lang::test]
+        fn buy_bundle_pays_royalties_and_fee() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new_configured(Config {
+                fee_bps: 1_000,
+                fee_recipient: Some(accounts.eve),
+                ..Default::default()
+            });
+            assert_eq!(erc721.mint_to(accounts.django, 1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint_to(accounts.django, 2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_royalty(1, vec![(accounts.charlie, 500)]), Ok(()));
+            set_caller(accounts.django);
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_bundle_for_sale(vec![1, 2], 1_001), Ok(0));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(1_500);
+            assert_eq!(erc721.buy_bundle(0), Ok(()));
+            // token 1 carries 501 of the price, token 2 carries 500
+            assert_eq!(erc721.accrued_fees(), 50 + 50);
+            assert_eq!(erc721.royalties_earned_of(accounts.charlie), 25);
+            assert_eq!(get_balance(accounts.charlie), 1_000 + 25);
+            assert_eq!(get_balance(accounts.django), 1_001 - 100 - 25);
+            // overpayment is returned
+            assert_eq!(get_balance(accounts.bob), 1_000 + 499);
+        }
+
+        #[ink_lang::test]
+        fn buy_bundle_fails_when_seller_lost_token() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+            assert_eq!(erc721.publish_bundle_for_sale(vec![1, 2], 20), Ok(0));
+            // seller gives away one token of the bundle
+            assert_eq!(erc721.transfer(accounts.eve, 2), Ok(()));
+
+            set_caller(accounts.bob);
+            ink_env::test::set_value_transferred::<ink_env::DefaultEnvironment>(20);
+            assert_eq!(erc721.buy_bundle(0), Err(Error::NotApproved));
+
+            // nothing moved
+            assert_eq!(erc721.owner_of(1), Some(accounts.alice));
+            assert_eq!(erc721.owner_of(2), Some(accounts.eve));
+            assert_eq!(erc721.balance_of(accounts.bob), 0);
+            assert_eq!(erc721.get_bundle(0), Some((vec![1, 2], 20)));
+        }
+
+        #[ink_lang::test]
+        fn floor_price_works() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>();
+            let contract = set_contract_account();
+            let mut erc721 = Erc721::new();
+            assert_eq!(erc721.set_approval_for_all(contract, true), Ok(()));
+
+            assert_eq!(erc721.mint(1, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(2, NftData { poebat: None }), Ok(()));
+            assert_eq!(erc721.mint(3, NftData { poebat: None }), Ok(()));
+
+            // only owner of the collection can set floor
+            set_caller(accounts.bob);
+            assert_eq!(erc721.set_floor_price(Some(10)), Err(Error::NotOwner));
+            set_caller(accounts.alice);
+            assert_eq!(erc721.set_floor_price(Some(10)), Ok(()));
+            assert_eq!(erc721.floor_price(), Some(10));
+
+            // exactly the floor is allowed
+            assert_eq!(erc721.publish_for_sale(1, 10), Ok(()));
+            // below the floor is rejected
+            assert_eq!(erc721.publish_for_sale(2, 9), Err(Error::BelowFloor));
+            assert_eq!(erc721.update_price(1, 9), Err(Error::BelowFloor));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 1, price: 10, on_hold: false}]);
+
+            // disabled floor allows any price
+            assert_eq!(erc721.set_floor_price(None), Ok(()));
+            assert_eq!(erc721.publish_for_sale(2, 1), Ok(()));
+            assert_eq!(erc721.update_price(1, 2), Ok(()));
+            assert_eq!(erc721.get_tokens_for_sale(), vec![ForSale{id: 1, price: 2, on_hold: false}, ForSale{id: 2, price: 1, on_hold: false}]);
+        }
+
         fn set_caller(sender: AccountId) {
             ink_env::test::set_caller::<ink_env::DefaultEnvironment>(sender);
         }
+
+        fn keypair(seed: u8) -> (secp256k1::SecretKey, AccountId) {
+            let key = secp256k1::SecretKey::from_slice(&[seed; 32]).unwrap();
+            let public_key = secp256k1::PublicKey::from_secret_key(secp256k1::SECP256K1, &key).serialize();
+            let mut account = [0; 32];
+            ink_env::hash_bytes::<ink_env::hash::Blake2x256>(&public_key, &mut account);
+            (key, AccountId::from(account))
+        }
+
+        fn sign(key: &secp256k1::SecretKey, message_hash: [u8; 32]) -> [u8; 65] {
+            let message = secp256k1::Message::from_slice(&message_hash).unwrap();
+            let (recovery_id, compact) = secp256k1::SECP256K1
+                .sign_ecdsa_recoverable(&message, key)
+                .serialize_compact();
+            let mut signature = [0; 65];
+            signature[..64].copy_from_slice(&compact);
+            signature[64] = recovery_id.to_i32() as u8;
+            signature
+        }
+
+        std::thread_local! {
+            static EXTERNAL_OWNERS: core::cell::RefCell<std::collections::BTreeMap<(AccountId, u32), AccountId>> =
+                Default::default();
+        }
+
+        /// External ERC-721 mock used instead of cross-contract calls.
+        pub(super) struct ExternalErc721Mock;
+
+        impl ExternalErc721Mock {
+            fn mint(contract: AccountId, id: u32, to: AccountId) {
+                EXTERNAL_OWNERS.with(|owners| owners.borrow_mut().insert((contract, id), to));
+            }
+
+            fn owner_of(contract: AccountId, id: u32) -> Option<AccountId> {
+                EXTERNAL_OWNERS.with(|owners| owners.borrow().get(&(contract, id)).copied())
+            }
+
+            pub(super) fn transfer_from(contract: AccountId, from: AccountId, to: AccountId, id: u32) -> Result<(), Error> {
+                if Self::owner_of(contract, id) != Some(from) {
+                    return Err(Error::CannotTransferToken);
+                }
+                Self::mint(contract, id, to);
+                Ok(())
+            }
+        }
+
+        std::thread_local! {
+            static PSP22_BALANCES: core::cell::RefCell<std::collections::BTreeMap<(AccountId, AccountId), Balance>> =
+                Default::default();
+        }
+
+        /// PSP22 token mock used instead of cross-contract calls.
+        pub(super) struct Psp22Mock;
+
+        impl Psp22Mock {
+            fn mint(token: AccountId, to: AccountId, amount: Balance) {
+                let balance = Self::balance_of(token, to) + amount;
+                PSP22_BALANCES.with(|balances| balances.borrow_mut().insert((token, to), balance));
+            }
+
+            fn balance_of(token: AccountId, owner: AccountId) -> Balance {
+                PSP22_BALANCES.with(|balances| balances.borrow().get(&(token, owner)).copied().unwrap_or(0))
+            }
+
+            pub(super) fn transfer(token: AccountId, from: AccountId, to: AccountId, amount: Balance) -> Result<(), Error> {
+                let from_balance = Self::balance_of(token, from).checked_sub(amount).ok_or(Error::CannotMakeTransfer)?;
+                PSP22_BALANCES.with(|balances| balances.borrow_mut().insert((token, from), from_balance));
+                Self::mint(token, to, amount);
+                Ok(())
+            }
+        }
+
+        std::thread_local! {
+            static HOOK_CALLS: core::cell::RefCell<Vec<(AccountId, AccountId, AccountId, TokenId)>> =
+                Default::default();
+            static FAILING_HOOKS: core::cell::RefCell<Vec<AccountId>> = Default::default();
+        }
+
+        /// Transfer hook contract mock used instead of cross-contract calls.
+        pub(super) struct TransferHookMock;
+
+        impl TransferHookMock {
+            fn fail(hook: AccountId) {
+                FAILING_HOOKS.with(|hooks| hooks.borrow_mut().push(hook));
+            }
+
+            fn calls(hook: AccountId) -> Vec<(AccountId, AccountId, TokenId)> {
+                HOOK_CALLS.with(|calls| {
+                    calls
+                        .borrow()
+                        .iter()
+                        .filter(|(callee, ..)| *callee == hook)
+                        .map(|(_, from, to, id)| (*from, *to, *id))
+                        .collect()
+                })
+            }
+
+            pub(super) fn on_transfer(hook: AccountId, from: AccountId, to: AccountId, id: TokenId) -> Result<(), Error> {
+                if FAILING_HOOKS.with(|hooks| hooks.borrow().contains(&hook)) {
+                    return Err(Error::NotAllowed);
+                }
+                HOOK_CALLS.with(|calls| calls.borrow_mut().push((hook, from, to, id)));
+                Ok(())
+            }
+        }
+
+        /// Sets account of the contract distinct from the default accounts, must be called before `Erc721::new`.
+        fn set_contract_account() -> AccountId {
+            let contract = AccountId::from([0xff; 32]);
+            ink_env::test::set_callee::<ink_env::DefaultEnvironment>(contract);
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(contract, 1_000_000);
+            contract
+        }
+
+        fn advance_blocks(count: u32) {
+            for _ in 0..count {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+        }
+
+        fn get_balance(account: AccountId) -> Balance {
+            ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(account).unwrap_or_default()
+        }
     }
 }